@@ -0,0 +1,350 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Saves pages and their essential assets (stylesheets, icons and images)
+//! for `history archive`.
+//!
+//! Every file is named after the SHA-256 of its content, so assets shared
+//! by several pages are kept once.
+
+use crate::error::Error;
+use crate::fetch::Fetch;
+use crate::manifest::ArchiveFormat;
+use chrono::prelude::*;
+use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+/// An archived page.
+#[derive(Debug)]
+pub struct Saved {
+    /// The hash of the archived file.
+    pub hash: String,
+    /// The assets that couldn't be fetched.
+    pub missing: Vec<(String, Error)>,
+}
+
+/// Saves the page at `url` with its assets into `dir`.
+///
+/// An HTML archive links the page to the saved assets; a WARC archive
+/// keeps every response as fetched in a single file.
+pub fn save<F: Fetch>(
+    fetcher: &F,
+    url: &str,
+    dir: &Path,
+    format: ArchiveFormat,
+) -> Result<Saved, Error> {
+    let base =
+        Url::parse(url).map_err(|err| Error::usage(format!("Invalid URL {}: {}", url, err)))?;
+    let page = fetcher.fetch(url)?;
+    let html = String::from_utf8_lossy(&page).into_owned();
+    let mut missing = Vec::new();
+    let mut fetched = Vec::new();
+
+    for (reference, asset) in assets(&html, &base) {
+        match fetcher.fetch(asset.as_str()) {
+            Ok(bytes) => fetched.push((reference, asset, bytes)),
+            Err(err) => missing.push((asset.to_string(), err)),
+        }
+    }
+
+    fs::create_dir_all(dir).map_err(|err| Error::from(err).in_file(dir))?;
+
+    let hash = match format {
+        ArchiveFormat::Html => {
+            let mut html = html;
+            for (reference, asset, bytes) in &fetched {
+                let (extension, _) = media(asset, bytes);
+                let name = format!("{}.{}", write(dir, bytes, extension)?, extension);
+                html = relink(&html, reference, &name);
+            }
+            write(dir, html.as_bytes(), "html")?
+        }
+        ArchiveFormat::Warc => {
+            let date = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            let info = format!(
+                "software: curator/{}\r\nformat: WARC File Format 1.1\r\n",
+                env!("CARGO_PKG_VERSION")
+            );
+            let mut warc = Vec::new();
+            warc_record(
+                &mut warc,
+                "warcinfo",
+                None,
+                "application/warc-fields",
+                info.as_bytes(),
+                &date,
+            );
+            warc_record(&mut warc, "resource", Some(url), "text/html", &page, &date);
+            for (_, asset, bytes) in &fetched {
+                let (_, media_type) = media(asset, bytes);
+                warc_record(
+                    &mut warc,
+                    "resource",
+                    Some(asset.as_str()),
+                    media_type,
+                    bytes,
+                    &date,
+                );
+            }
+            write(dir, &warc, "warc")?
+        }
+    };
+
+    Ok(Saved { hash, missing })
+}
+
+/// The assets referenced by the page, as written in it and resolved.
+///
+/// Local files are only fetched for local pages, so a remote page can't copy
+/// them into the archive.
+fn assets(html: &str, base: &Url) -> Vec<(String, Url)> {
+    let local = base.scheme() == "file";
+    let document = Html::parse_document(html);
+    let base_selector = Selector::parse("base[href]").expect("A valid selector");
+    let selector =
+        Selector::parse(r#"link[rel~="stylesheet"][href], link[rel~="icon"][href], img[src]"#)
+            .expect("A valid selector");
+    let base = document
+        .select(&base_selector)
+        .next()
+        .and_then(|element| element.value().attr("href"))
+        .and_then(|href| base.join(href).ok())
+        .unwrap_or_else(|| base.clone());
+    let mut assets: Vec<(String, Url)> = Vec::new();
+
+    for element in document.select(&selector) {
+        let attr = if element.value().name() == "img" {
+            "src"
+        } else {
+            "href"
+        };
+        let reference = match element.value().attr(attr) {
+            Some(reference) if !reference.trim().is_empty() => reference,
+            _ => continue,
+        };
+
+        if let Ok(asset) = base.join(reference.trim()) {
+            let fetchable = match asset.scheme() {
+                "http" | "https" => true,
+                "file" => local,
+                _ => false,
+            };
+            if fetchable && !assets.iter().any(|(r, _)| r == reference) {
+                assets.push((reference.to_string(), asset));
+            }
+        }
+    }
+
+    assets
+}
+
+/// Points the quoted references in the page to the saved file.
+fn relink(html: &str, reference: &str, name: &str) -> String {
+    let mut html = html.to_string();
+
+    for written in &[reference.to_string(), reference.replace('&', "&amp;")] {
+        for quote in &['"', '\''] {
+            html = html.replace(
+                &format!("={}{}{}", quote, written, quote),
+                &format!("={}{}{}", quote, name, quote),
+            );
+        }
+    }
+
+    html
+}
+
+/// Writes the content into `dir` as `<hash>.<extension>`, returning the
+/// hash.
+fn write(dir: &Path, bytes: &[u8], extension: &str) -> Result<String, Error> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let path = dir.join(format!("{}.{}", hash, extension));
+
+    if !path.exists() {
+        fs::write(&path, bytes).map_err(|err| Error::from(err).in_file(&path))?;
+    }
+
+    Ok(hash)
+}
+
+const MEDIA: &[(&str, &str)] = &[
+    ("css", "text/css"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("ico", "image/x-icon"),
+];
+
+/// The file extension and media type of an asset, from its URL or else
+/// its first bytes.
+fn media(url: &Url, bytes: &[u8]) -> (&'static str, &'static str) {
+    let extension = url
+        .path()
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+
+    if let Some(known) = extension.and_then(|ext| MEDIA.iter().find(|(e, _)| *e == ext)) {
+        return *known;
+    }
+
+    let sniffed = if bytes.starts_with(b"\x89PNG") {
+        "png"
+    } else if bytes.starts_with(b"\xff\xd8") {
+        "jpg"
+    } else if bytes.starts_with(b"GIF8") {
+        "gif"
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        "webp"
+    } else if bytes.starts_with(b"<svg") || bytes.starts_with(b"<?xml") {
+        "svg"
+    } else {
+        return ("bin", "application/octet-stream");
+    };
+
+    *MEDIA
+        .iter()
+        .find(|(e, _)| *e == sniffed)
+        .expect("A known media type")
+}
+
+fn warc_record(
+    warc: &mut Vec<u8>,
+    type_: &str,
+    uri: Option<&str>,
+    media_type: &str,
+    block: &[u8],
+    date: &str,
+) {
+    let digest = format!("{:x}", Sha256::digest(block));
+    let id = format!(
+        "{:x}",
+        Sha256::digest(format!("{}{}{}", type_, uri.unwrap_or(""), digest))
+    );
+
+    warc.extend_from_slice(b"WARC/1.1\r\n");
+    warc.extend_from_slice(format!("WARC-Type: {}\r\n", type_).as_bytes());
+    warc.extend_from_slice(format!("WARC-Record-ID: <urn:sha256:{}>\r\n", id).as_bytes());
+    warc.extend_from_slice(format!("WARC-Date: {}\r\n", date).as_bytes());
+    if let Some(uri) = uri {
+        warc.extend_from_slice(format!("WARC-Target-URI: {}\r\n", uri).as_bytes());
+    }
+    warc.extend_from_slice(format!("WARC-Block-Digest: sha256:{}\r\n", digest).as_bytes());
+    warc.extend_from_slice(format!("Content-Type: {}\r\n", media_type).as_bytes());
+    warc.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+    warc.extend_from_slice(block);
+    warc.extend_from_slice(b"\r\n\r\n");
+}
+
+/// The first HTML resource of a WARC file, i.e. the archived page.
+pub fn warc_page(warc: &[u8]) -> Option<&[u8]> {
+    let mut rest = warc;
+
+    while !rest.is_empty() {
+        let end = rest.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&rest[..end]);
+        let header = |name: &str| {
+            headers.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                if key.eq_ignore_ascii_case(name) {
+                    Some(value.trim().to_string())
+                } else {
+                    None
+                }
+            })
+        };
+        let len: usize = header("Content-Length")?.parse().ok()?;
+        let block = rest.get(end + 4..end + 4 + len)?;
+
+        if header("WARC-Type").as_deref() == Some("resource")
+            && header("Content-Type").is_some_and(|t| t.starts_with("text/html"))
+        {
+            return Some(block);
+        }
+
+        rest = rest.get(end + 4 + len + 4..)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::HttpFetcher;
+    use std::thread;
+    use tiny_http::{Response, Server};
+
+    const PAGE: &str = r#"<html><head><link rel="stylesheet" href="style.css">
+        </head><body><img src="/a.png?x=1&amp;y=2"><img src='gone.png'></body></html>"#;
+
+    #[test]
+    fn local_assets_of_remote_pages() {
+        let html = r#"<img src="file:///home/me/.ssh/id_rsa"><img src="a.png">"#;
+        let remote = Url::parse("https://a.org/page").unwrap();
+        let local = Url::parse("file:///home/me/page.html").unwrap();
+
+        let found: Vec<String> = assets(html, &remote)
+            .into_iter()
+            .map(|(_, url)| url.to_string())
+            .collect();
+        assert_eq!(found, ["https://a.org/a.png"]);
+
+        let based = format!(r#"<base href="file:///home/me/">{}"#, html);
+        assert_eq!(assets(&based, &remote).len(), 0);
+        assert_eq!(assets(html, &local).len(), 2);
+    }
+
+    /// Serves the page and its assets until the test process ends.
+    fn serve() -> String {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = match request.url() {
+                    "/page" => Response::from_string(PAGE),
+                    "/style.css" => Response::from_string("body { color: red }"),
+                    "/a.png?x=1&y=2" => Response::from_data(&b"\x89PNG fake"[..]),
+                    _ => Response::from_string("").with_status_code(404),
+                };
+                let _ = request.respond(response);
+            }
+        });
+
+        format!("http://127.0.0.1:{}/page", port)
+    }
+
+    #[test]
+    fn save_html_and_warc() {
+        let url = serve();
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let fetcher = HttpFetcher::new();
+
+        let saved = save(&fetcher, &url, dir, ArchiveFormat::Html).unwrap();
+        let html = fs::read_to_string(dir.join(format!("{}.html", saved.hash))).unwrap();
+        let css = format!("{:x}.css", Sha256::digest(b"body { color: red }"));
+
+        assert!(html.contains(&format!("href=\"{}\"", css)));
+        assert!(html.contains(".png\""));
+        assert!(html.contains("src='gone.png'"));
+        assert_eq!(saved.missing.len(), 1);
+
+        let saved = save(&fetcher, &url, dir, ArchiveFormat::Warc).unwrap();
+        let warc = fs::read(dir.join(format!("{}.warc", saved.hash))).unwrap();
+
+        assert_eq!(warc_page(&warc), Some(PAGE.as_bytes()));
+    }
+}
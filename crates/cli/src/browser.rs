@@ -0,0 +1,29 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use std::process::{Command, Stdio};
+
+/// Opens a URL or a file with the default application of the system.
+pub fn open(target: &str) -> Result<(), Error> {
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("cmd", &["/C", "start", ""])
+    } else {
+        ("xdg-open", &[])
+    };
+
+    Command::new(program)
+        .args(args)
+        .arg(target)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| Error::unavailable(format!("Couldn't run {}: {}", program, err)))?;
+
+    Ok(())
+}
@@ -0,0 +1,390 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Shell completions.
+//!
+//! The scripts are generated from the clap definitions and hand the words
+//! typed so far to the hidden `curator __complete` command, which answers
+//! with values from the cellar when the argument being completed refers to
+//! its data, e.g. a source id for `--origin`. Otherwise the script falls
+//! back to the generated completions.
+
+use crate::config::UserConfig;
+use crate::error::Error;
+use crate::history;
+use crate::ideas;
+use crate::manifest::Manifest;
+use crate::store;
+use clap::{Command, Parser};
+use clap_complete::Shell;
+use curator_sketch::source;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// The number of recent URLs offered.
+const RECENT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Sources,
+    Tags,
+    Urls,
+    Ideas,
+    Cellars,
+}
+
+/// The arguments completed with values from the cellar, by subcommand path
+/// and argument id. An empty path matches every subcommand.
+const LIVE: &[(&str, &str, Kind)] = &[
+    ("", "cellar", Kind::Cellars),
+    ("", "tag", Kind::Tags),
+    ("history list", "origin", Kind::Sources),
+    ("sources poll", "id", Kind::Sources),
+    ("ideas export", "idea", Kind::Ideas),
+    ("history copy", "url", Kind::Urls),
+    ("history copy", "to", Kind::Cellars),
+    ("history archive", "url", Kind::Urls),
+    ("history open", "url", Kind::Urls),
+    ("history extract", "url", Kind::Urls),
+    ("history show", "url", Kind::Urls),
+    ("history related", "url", Kind::Urls),
+    ("history link", "from", Kind::Urls),
+    ("history link", "to", Kind::Urls),
+    ("history unlink", "from", Kind::Urls),
+    ("history unlink", "to", Kind::Urls),
+    ("history annotate", "url", Kind::Urls),
+    ("history status", "url", Kind::Urls),
+    ("history rate", "url", Kind::Urls),
+    ("tags suggest", "url", Kind::Urls),
+];
+
+#[derive(Debug, Parser)]
+pub struct Completions {
+    /// The shell to generate the completion script for.
+    #[clap(value_parser = ["bash", "zsh", "fish"])]
+    shell: String,
+}
+
+impl Completions {
+    pub fn run(&self, mut cmd: Command) -> Result<(), Error> {
+        let shell: Shell = self.shell.parse().map_err(Error::usage)?;
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut cmd, "curator", &mut script);
+        let mut script = String::from_utf8_lossy(&script).into_owned();
+
+        match shell {
+            Shell::Bash => script.push_str(BASH),
+            Shell::Zsh => {
+                // The generated dispatch is replaced by one going through the
+                // live completions first.
+                if let Some(idx) = script.rfind("if [ \"$funcstack[1]\" = \"_curator\" ]") {
+                    script.truncate(idx);
+                }
+                script.push_str(ZSH);
+            }
+            _ => script.push_str(FISH),
+        }
+
+        print!("{}", script);
+
+        Ok(())
+    }
+}
+
+const BASH: &str = r#"
+_curator_live() {
+    local line="${COMP_LINE:0:COMP_POINT}"
+    local cur="${line##*[[:space:]]}"
+    local values
+
+    if values="$(curator __complete --line "$line" 2>/dev/null)"; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$values" -- "$cur"))
+
+        # Bash splits words on colons, as found in URLs.
+        if [[ "$cur" == *:* && "$COMP_WORDBREAKS" == *:* ]]; then
+            local colon="${cur%"${cur##*:}"}"
+            COMPREPLY=("${COMPREPLY[@]#"$colon"}")
+        fi
+
+        return 0
+    fi
+
+    _curator "$@"
+}
+
+complete -F _curator_live -o bashdefault -o default curator
+"#;
+
+const ZSH: &str = r#"
+_curator_live() {
+    local out
+
+    if out="$(curator __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)"; then
+        local -a values
+        values=(${(f)out})
+        compadd -a values
+        return
+    fi
+
+    _curator "$@"
+}
+
+if [ "$funcstack[1]" = "_curator" ]; then
+    _curator_live "$@"
+else
+    compdef _curator_live curator
+fi
+"#;
+
+const FISH: &str = r#"
+function __curator_live
+    curator __complete --describe -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null
+end
+
+complete -c curator -f -n '__curator_live >/dev/null' -a '(__curator_live)'
+"#;
+
+#[derive(Debug, Parser)]
+pub struct Complete {
+    /// Adds a tab-separated description to every value.
+    #[clap(long)]
+    describe: bool,
+    /// The command line up to the cursor, instead of the words.
+    #[clap(long, conflicts_with = "words")]
+    line: Option<String>,
+    /// The words after `curator`, the last one being completed.
+    #[clap(allow_hyphen_values = true)]
+    words: Vec<String>,
+}
+
+impl Complete {
+    /// Prints the values for the word being completed, exiting with 1 when
+    /// it isn't completed from the cellar.
+    pub fn run(&self, cmd: Command) -> Result<(), Error> {
+        let words = match &self.line {
+            Some(line) => {
+                let mut words: Vec<String> =
+                    line.split_whitespace().skip(1).map(String::from).collect();
+                if line.ends_with(char::is_whitespace) {
+                    words.push(String::new());
+                }
+                words
+            }
+            None => self.words.clone(),
+        };
+
+        let target = resolve(&cmd, &words);
+        let values = match target.kind {
+            Some(Kind::Cellars) => UserConfig::load().map(|config| {
+                config
+                    .cellars()
+                    .iter()
+                    .map(|(name, path)| (name.clone(), path.display().to_string()))
+                    .collect()
+            }),
+            Some(kind) => Manifest::discover(
+                target.flags.get("manifest_path").map(String::as_str),
+                target.flags.get("cellar").map(String::as_str),
+            )
+            .and_then(|manifest| values(&manifest, kind)),
+            None => exit(1),
+        };
+
+        for (value, description) in values.unwrap_or_else(|_| exit(1)) {
+            if self.describe && !description.is_empty() {
+                println!("{}\t{}", value, description.replace(['\t', '\n'], " "));
+            } else {
+                println!("{}", value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the last word completes, and the flag values seen before it.
+#[derive(Debug, Default)]
+struct Target {
+    kind: Option<Kind>,
+    flags: HashMap<String, String>,
+}
+
+/// Walks the words down the subcommands to find the argument the last word
+/// is a value for.
+fn resolve(root: &Command, words: &[String]) -> Target {
+    let mut target = Target::default();
+    let (current, typed) = match words.split_last() {
+        Some(split) => split,
+        None => return target,
+    };
+    let mut stack = vec![root];
+    let mut path: Vec<&str> = Vec::new();
+    let mut positional = 0;
+    let mut pending: Option<String> = None;
+
+    for word in typed {
+        let cmd = stack[stack.len() - 1];
+
+        if let Some(id) = pending.take() {
+            target.flags.insert(id, word.clone());
+        } else if let Some(flag) = word.strip_prefix('-').filter(|_| word.len() > 1) {
+            if !flag.contains('=') {
+                pending = find_flag(&stack, flag);
+            }
+        } else if let Some(sub) = cmd
+            .get_subcommands()
+            .find(|sub| sub.get_name() == word || sub.get_all_aliases().any(|a| a == word))
+        {
+            stack.push(sub);
+            path.push(sub.get_name());
+            positional = 0;
+        } else {
+            positional += 1;
+        }
+    }
+
+    let cmd = stack[stack.len() - 1];
+    let id = match pending {
+        Some(id) => Some(id),
+        None if current.starts_with('-') => None,
+        None => cmd
+            .get_positionals()
+            .nth(positional)
+            .map(|arg| arg.get_id().to_string()),
+    };
+
+    let path = path.join(" ");
+    target.kind = id.and_then(|id| {
+        LIVE.iter()
+            .find(|(p, arg, _)| (p.is_empty() || *p == path) && *arg == id)
+            .map(|(_, _, kind)| *kind)
+    });
+
+    target
+}
+
+/// The id of the flag taking a value, looked up from the innermost
+/// subcommand out.
+fn find_flag(stack: &[&Command], flag: &str) -> Option<String> {
+    stack.iter().rev().find_map(|cmd| {
+        cmd.get_arguments()
+            .find(|arg| match flag.strip_prefix('-') {
+                Some(long) => arg.get_long() == Some(long),
+                None => flag.len() == 1 && arg.get_short() == flag.chars().next(),
+            })
+            .filter(|arg| arg.get_action().takes_values())
+            .map(|arg| arg.get_id().to_string())
+    })
+}
+
+/// The values of a kind with their descriptions, the most relevant first.
+fn values(manifest: &Manifest, kind: Kind) -> Result<Vec<(String, String)>, Error> {
+    let values = match kind {
+        Kind::Sources => store::read(manifest.sources_path()?, source::from_reader)?
+            .into_iter()
+            .map(|src| (src.id().to_string(), src.url().to_string()))
+            .collect(),
+        Kind::Tags => {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for record in history::load(manifest.history_path()?)? {
+                for tag in record.tags() {
+                    *counts.entry(tag).or_insert(0) += 1;
+                }
+            }
+            let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            counts
+                .into_iter()
+                .map(|(tag, count)| (tag, format!("{} records", count)))
+                .collect()
+        }
+        Kind::Urls => {
+            let mut records = history::load(manifest.history_path()?)?;
+            records.sort_by_key(|record| std::cmp::Reverse(record.date()));
+            records
+                .into_iter()
+                .take(RECENT)
+                .map(|record| (record.url().to_string(), record.title().to_string()))
+                .collect()
+        }
+        Kind::Ideas => ideas::load(manifest.ideas_path()?)?
+            .into_iter()
+            .rev()
+            .map(|idea| (idea.id().to_string(), idea.summary().to_string()))
+            .collect(),
+        Kind::Cellars => Vec::new(),
+    };
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, CommandFactory};
+
+    fn words(line: &str) -> Vec<String> {
+        line.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn resolve_arguments() {
+        let root = Command::new("curator")
+            .arg(Arg::new("cellar").long("cellar").short('c'))
+            .subcommand(
+                Command::new("history").alias("h").subcommand(
+                    Command::new("copy")
+                        .arg(Arg::new("url"))
+                        .arg(Arg::new("to").long("to")),
+                ),
+            );
+
+        assert_eq!(
+            resolve(&root, &words("h copy https://a")).kind,
+            Some(Kind::Urls)
+        );
+        assert_eq!(
+            resolve(&root, &words("history copy x --to ")).kind,
+            Some(Kind::Cellars)
+        );
+
+        let target = resolve(&root, &words("-c team history copy --"));
+        assert_eq!(target.kind, None);
+        assert_eq!(target.flags.get("cellar").map(String::as_str), Some("team"));
+        assert_eq!(resolve(&root, &words("history ")).kind, None);
+    }
+
+    #[test]
+    fn live_arguments_exist() {
+        let root = crate::Curator::command();
+
+        for (path, id, _) in LIVE {
+            let cmds: Vec<&Command> = if path.is_empty() {
+                let mut all = vec![&root];
+                let mut idx = 0;
+                while idx < all.len() {
+                    all.extend(all[idx].get_subcommands());
+                    idx += 1;
+                }
+                all
+            } else {
+                let found = path.split(' ').try_fold(&root, |cmd, name| {
+                    cmd.get_subcommands().find(|sub| sub.get_name() == name)
+                });
+                found.into_iter().collect()
+            };
+
+            assert!(
+                cmds.iter()
+                    .any(|cmd| cmd.get_arguments().any(|arg| arg.get_id() == *id)),
+                "No argument {} in '{}'",
+                id,
+                path
+            );
+        }
+    }
+}
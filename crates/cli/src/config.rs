@@ -0,0 +1,342 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! User-level configuration and manifest discovery.
+//!
+//! The manifest is looked up, in order, from the `--manifest-path` flag, the
+//! `--cellar` flag naming a cellar registered under `[cellars]`, the
+//! `CURATOR_MANIFEST` environment variable, the current directory and its
+//! parents and, finally, the `manifest` key of the user configuration at
+//! `$XDG_CONFIG_HOME/curator/config.toml`.
+
+use crate::error::{Error, ErrorKind};
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use clap::Parser;
+use curator_sketch::history::RecordBuilder;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use dialoguer::Editor;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_NAME: &str = "Cellar.toml";
+pub const MANIFEST_ENV: &str = "CURATOR_MANIFEST";
+
+/// Preferences that can be set in the user configuration and overridden by
+/// the `[settings]` section of a cellar manifest.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The editor to use instead of `$VISUAL` or `$EDITOR`.
+    editor: Option<String>,
+    theme: Option<ThemeName>,
+    /// Tags every new record and idea starts with.
+    default_tags: Option<Vec<String>>,
+    /// An IANA timezone, e.g. `Europe/London`, used to date new entries.
+    timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ThemeName {
+    Colorful,
+    Simple,
+}
+
+impl Settings {
+    /// Layers `other` on top of `self`, keeping the values `other` leaves
+    /// unset.
+    pub fn layered(self, other: Settings) -> Settings {
+        Settings {
+            editor: other.editor.or(self.editor),
+            theme: other.theme.or(self.theme),
+            default_tags: other.default_tags.or(self.default_tags),
+            timezone: other.timezone.or(self.timezone),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(tz) = &self.timezone {
+            tz.parse::<Tz>().map_err(|_| {
+                Error::config(format!("Invalid 'timezone': unknown timezone '{}'", tz))
+                    .with_hint("Use an IANA timezone name, e.g. 'Europe/London'")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn editor(&self) -> Editor {
+        let mut editor = Editor::new();
+
+        if let Some(executable) = &self.editor {
+            editor.executable(executable);
+        }
+
+        editor
+    }
+
+    pub fn theme(&self) -> Box<dyn Theme> {
+        match self.theme {
+            Some(ThemeName::Simple) => Box::new(SimpleTheme),
+            _ => Box::new(ColorfulTheme::default()),
+        }
+    }
+
+    pub fn default_tags(&self) -> Vec<&str> {
+        self.default_tags
+            .iter()
+            .flatten()
+            .map(|tag| tag.as_str())
+            .collect()
+    }
+
+    /// Today in the configured timezone, UTC otherwise.
+    pub fn today(&self) -> NaiveDate {
+        match self
+            .timezone
+            .as_deref()
+            .and_then(|tz| tz.parse::<Tz>().ok())
+        {
+            Some(tz) => Utc::now().with_timezone(&tz).date_naive(),
+            None => Utc::now().date_naive(),
+        }
+    }
+
+    /// Applies the date and default tags to a new record draft.
+    pub fn draft(&self, builder: RecordBuilder) -> RecordBuilder {
+        let existing = builder.tags();
+
+        self.default_tags()
+            .into_iter()
+            .filter(|tag| !existing.iter().any(|t| t == tag))
+            .fold(builder.with_date(self.today()), |builder, tag| {
+                builder.with_tag(tag)
+            })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// The manifest to use when none is found from the current directory.
+    manifest: Option<PathBuf>,
+    /// Manifests registered by name, e.g. `team = "~/team/Cellar.toml"`.
+    #[serde(default)]
+    cellars: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    settings: Settings,
+    /// The bearer token `curator serve` requires from its clients.
+    serve_token: Option<String>,
+}
+
+impl UserConfig {
+    /// Loads the user configuration, empty when there is none.
+    pub fn load() -> Result<UserConfig, Error> {
+        let path = match config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(UserConfig::default()),
+        };
+        let content = fs::read_to_string(&path).map_err(|err| Error::from(err).in_file(&path))?;
+        let mut config: UserConfig = toml::from_str(&content)
+            .map_err(|err| Error::from(err).with_kind(ErrorKind::Config).in_file(&path))?;
+        config
+            .settings
+            .validate()
+            .map_err(|err| err.in_file(&path))?;
+
+        if let Some(dir) = path.parent() {
+            config.manifest = config.manifest.map(|p| dir.join(expand_home(&p)));
+
+            for manifest in config.cellars.values_mut() {
+                *manifest = dir.join(expand_home(manifest));
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn cellars(&self) -> &BTreeMap<String, PathBuf> {
+        &self.cellars
+    }
+
+    /// The manifest of the cellar registered under `name`.
+    pub fn cellar(&self, name: &str) -> Result<&Path, Error> {
+        self.cellars.get(name).map(|p| p.as_path()).ok_or_else(|| {
+            let known = self.cellars.keys().cloned().collect::<Vec<_>>();
+            let hint = if known.is_empty() {
+                "Register cellars under [cellars] in the user configuration".to_string()
+            } else {
+                format!("Registered cellars: {}", known.join(", "))
+            };
+
+            Error::usage(format!("Unknown cellar '{}'", name)).with_hint(hint)
+        })
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn serve_token(&self) -> Option<&str> {
+        self.serve_token
+            .as_deref()
+            .filter(|token| !token.is_empty())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Cellars;
+
+impl Cellars {
+    pub fn run(&self) -> Result<(), Error> {
+        let config = UserConfig::load()?;
+
+        if config.cellars().is_empty() {
+            println!(
+                "No cellars registered. Add a [cellars] table to {}",
+                config_path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "the user configuration".to_string())
+            );
+        }
+
+        for (name, path) in config.cellars() {
+            println!("{:12}  {}", name, path.display());
+        }
+
+        Ok(())
+    }
+}
+
+/// The path to the user configuration file.
+pub fn config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join("curator").join("config.toml"))
+}
+
+/// Finds the manifest to use following the discovery order, unless a path
+/// or a registered cellar name is given explicitly.
+pub fn locate_manifest(
+    explicit: Option<&str>,
+    cellar: Option<&str>,
+    config: &UserConfig,
+) -> Result<PathBuf, Error> {
+    if let Some(path) = explicit {
+        return Ok(PathBuf::from(path));
+    }
+
+    if let Some(name) = cellar {
+        return Ok(config.cellar(name)?.to_path_buf());
+    }
+
+    if let Some(path) = env::var_os(MANIFEST_ENV).filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let cwd = env::current_dir()?;
+    if let Some(path) = find_upwards(&cwd) {
+        return Ok(path);
+    }
+
+    config.manifest.clone().ok_or_else(|| {
+        Error::config(format!(
+            "No {} found in {} or its parents",
+            MANIFEST_NAME,
+            cwd.display()
+        ))
+        .with_hint(format!(
+            "Pass --manifest-path or --cellar, set {} or run 'curator init'",
+            MANIFEST_ENV
+        ))
+    })
+}
+
+/// Walks up from `start` looking for a manifest.
+fn find_upwards(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(MANIFEST_NAME))
+        .find(|path| path.is_file())
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_in_parent() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        let nested = root.join("a").join("b");
+        fs::create_dir_all(&nested)?;
+        fs::write(root.join(MANIFEST_NAME), "")?;
+
+        let actual = find_upwards(&nested);
+
+        assert_eq!(actual, Some(root.join(MANIFEST_NAME)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cellar_settings_win() -> Result<(), Box<dyn std::error::Error>> {
+        let user: Settings = toml::from_str(
+            r#"
+            editor = "vi"
+            default_tags = ["a"]
+            timezone = "Europe/London"
+            "#,
+        )?;
+        let cellar: Settings = toml::from_str(r#"editor = "nano""#)?;
+        let actual = user.layered(cellar);
+
+        assert_eq!(actual.editor.as_deref(), Some("nano"));
+        assert_eq!(actual.default_tags(), vec!["a"]);
+        assert_eq!(actual.timezone.as_deref(), Some("Europe/London"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn named_cellar() -> Result<(), Box<dyn std::error::Error>> {
+        let config: UserConfig = toml::from_str(
+            r#"
+            [cellars]
+            team = "/srv/team/Cellar.toml"
+            "#,
+        )?;
+        let actual = locate_manifest(None, Some("team"), &config)?;
+
+        assert_eq!(actual, PathBuf::from("/srv/team/Cellar.toml"));
+        assert!(locate_manifest(None, Some("nope"), &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_timezone() -> Result<(), Box<dyn std::error::Error>> {
+        let settings: Settings = toml::from_str(r#"timezone = "Mars/Olympus""#)?;
+
+        assert!(settings.validate().is_err());
+
+        Ok(())
+    }
+}
@@ -5,74 +5,380 @@
 // according to those terms.
 
 use console::{Style, Term};
-use curator_sketch::{history::RecordError, source::SourceError};
+use curator_sketch::{
+    annotation::AnnotationError, archive::ArchiveError, history::RecordError, idea::IdeaError,
+    import::ImportError, inbox::InboxError, source::SourceError, text::TextError,
+};
 use lazy_static::lazy_static;
+use std::path::{Path, PathBuf};
 use std::{error, fmt, io};
 
 lazy_static! {
     pub static ref TERM_ERR: Term = Term::stderr();
     pub static ref TERM_OUT: Term = Term::stdout();
+    static ref RED: Style = Style::new().for_stderr().red().bold();
     static ref YELLOW: Style = Style::new().for_stderr().yellow();
     pub static ref GREEN: Style = Style::new().for_stderr().green();
     pub static ref MAGENTA: Style = Style::new().for_stderr().magenta();
 }
 
+/// The category of an error, which decides the exit code.
+///
+/// Codes follow `sysexits.h` where there is a match.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    /// Anything not categorised.
+    Other,
+    /// The command was used wrongly, e.g. an unknown record or cellar.
+    Usage,
+    /// A store or an input file is malformed.
+    Data,
+    /// A required external service, like git or a feed server, failed.
+    Unavailable,
+    /// Reading or writing a file failed.
+    Io,
+    /// Another process holds the cellar lock.
+    Busy,
+    /// The manifest or the user configuration is wrong.
+    Config,
+    /// The user cancelled the operation.
+    Aborted,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Usage => 64,
+            ErrorKind::Data => 65,
+            ErrorKind::Unavailable => 69,
+            ErrorKind::Io => 74,
+            ErrorKind::Busy => 75,
+            ErrorKind::Config => 78,
+            ErrorKind::Aborted => 130,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Other => "other",
+            ErrorKind::Usage => "usage",
+            ErrorKind::Data => "data",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::Io => "io",
+            ErrorKind::Busy => "busy",
+            ErrorKind::Config => "config",
+            ErrorKind::Aborted => "aborted",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Where in a file an error comes from.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Location {
+    path: Option<PathBuf>,
+    /// 1-based line.
+    line: Option<u64>,
+    /// 1-based column, or field for CSV stores.
+    column: Option<u64>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display())?,
+            None => write!(f, "<input>")?,
+        }
+
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// Represents a CLI error.
 #[derive(PartialEq, Debug, Clone)]
-pub struct Error(String);
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+    location: Option<Location>,
+    hint: Option<String>,
+}
 
 impl Error {
-    pub fn new(msg: &str) -> Self {
-        Self(msg.to_string())
+    pub fn of<S: Into<String>>(kind: ErrorKind, msg: S) -> Self {
+        Error {
+            kind,
+            message: msg.into(),
+            location: None,
+            hint: None,
+        }
+    }
+
+    pub fn usage<S: Into<String>>(msg: S) -> Self {
+        Self::of(ErrorKind::Usage, msg)
+    }
+
+    pub fn data<S: Into<String>>(msg: S) -> Self {
+        Self::of(ErrorKind::Data, msg)
+    }
+
+    pub fn unavailable<S: Into<String>>(msg: S) -> Self {
+        Self::of(ErrorKind::Unavailable, msg)
+    }
+
+    pub fn busy<S: Into<String>>(msg: S) -> Self {
+        Self::of(ErrorKind::Busy, msg)
+    }
+
+    pub fn config<S: Into<String>>(msg: S) -> Self {
+        Self::of(ErrorKind::Config, msg)
+    }
+
+    pub fn aborted() -> Self {
+        Self::of(ErrorKind::Aborted, "Aborted")
+    }
+
+    /// Recategorises the error, e.g. a TOML error found in the manifest.
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Suggests what to do about the error.
+    pub fn with_hint<S: Into<String>>(mut self, hint: S) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Attributes the error to the given file, keeping any line and column
+    /// already known.
+    pub fn in_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let location = self.location.get_or_insert_with(Location::default);
+        if location.path.is_none() {
+            location.path = Some(path.as_ref().to_path_buf());
+        }
+        self
+    }
+
+    fn at(mut self, line: Option<u64>, column: Option<u64>) -> Self {
+        let location = self.location.get_or_insert_with(Location::default);
+        location.line = line;
+        location.column = column;
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+
+    /// Renders the error, its location and its hint to the standard error.
+    pub fn print_err(&self) -> io::Result<()> {
+        TERM_ERR.write_line(&format!("{} {}", RED.apply_to("error:"), self.message))?;
+
+        if let Some(location) = &self.location {
+            TERM_ERR.write_line(&format!("  {} {}", MAGENTA.apply_to("-->"), location))?;
+        }
+
+        if let Some(hint) = &self.hint {
+            TERM_ERR.write_line(&format!("  {} {}", YELLOW.apply_to("hint:"), hint))?;
+        }
+
+        Ok(())
     }
 }
 
 impl error::Error for Error {}
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}", location, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
 impl From<String> for Error {
     fn from(err: String) -> Error {
-        Error(err)
+        Error::of(ErrorKind::Other, err)
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error(format!("{}", err))
+        Error::of(ErrorKind::Io, err.to_string())
     }
 }
 
 impl From<csv::Error> for Error {
     fn from(err: csv::Error) -> Error {
-        Error(format!("{}", err))
+        let column = match err.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.field().map(|field| field + 1),
+            _ => None,
+        };
+        let line = err.position().map(|pos| pos.line());
+        let kind = match err.kind() {
+            csv::ErrorKind::Io(_) => ErrorKind::Io,
+            _ => ErrorKind::Data,
+        };
+        let message = match err.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.kind().to_string(),
+            csv::ErrorKind::UnequalLengths {
+                expected_len, len, ..
+            } => format!("expected {} fields, found {}", expected_len, len),
+            _ => err.to_string(),
+        };
+        let error = Error::of(kind, message);
+
+        match line {
+            Some(line) => error.at(Some(line), column),
+            None => error,
+        }
     }
 }
 
 impl From<toml::de::Error> for Error {
     fn from(err: toml::de::Error) -> Error {
-        Error(format!("{}", err))
+        let position = err.line_col();
+        let mut message = err.to_string();
+
+        // The position is rendered as part of the location instead.
+        if let Some(idx) = message.find(" at line ") {
+            message.truncate(idx);
+        }
+
+        let error = Error::data(message);
+
+        match position {
+            Some((line, column)) => error.at(Some(line as u64 + 1), Some(column as u64 + 1)),
+            None => error,
+        }
     }
 }
 
 impl From<toml::ser::Error> for Error {
     fn from(err: toml::ser::Error) -> Error {
-        Error(format!("{}", err))
+        Error::of(ErrorKind::Other, err.to_string())
     }
 }
 
 impl From<RecordError> for Error {
     fn from(err: RecordError) -> Error {
-        Error(format!("{}", err))
+        match err {
+            RecordError::Csv(err) => err.into(),
+            RecordError::Io(err) => err.into(),
+            err => Error::usage(err.to_string()),
+        }
+    }
+}
+
+impl From<AnnotationError> for Error {
+    fn from(err: AnnotationError) -> Error {
+        match err {
+            AnnotationError::Csv(err) => err.into(),
+            AnnotationError::Io(err) => err.into(),
+            err => Error::usage(err.to_string()),
+        }
+    }
+}
+
+impl From<ArchiveError> for Error {
+    fn from(err: ArchiveError) -> Error {
+        match err {
+            ArchiveError::Csv(err) => err.into(),
+            ArchiveError::Io(err) => err.into(),
+        }
+    }
+}
+
+impl From<TextError> for Error {
+    fn from(err: TextError) -> Error {
+        match err {
+            TextError::Csv(err) => err.into(),
+            TextError::Io(err) => err.into(),
+        }
+    }
+}
+
+impl From<IdeaError> for Error {
+    fn from(err: IdeaError) -> Error {
+        match err {
+            IdeaError::Csv(err) => err.into(),
+            IdeaError::Io(err) => err.into(),
+            err => Error::data(err.to_string()),
+        }
+    }
+}
+
+impl From<ImportError> for Error {
+    fn from(err: ImportError) -> Error {
+        match err {
+            ImportError::Csv(err) => err.into(),
+            ImportError::Io(err) => err.into(),
+            err => Error::data(err.to_string()),
+        }
+    }
+}
+
+impl From<InboxError> for Error {
+    fn from(err: InboxError) -> Error {
+        match err {
+            InboxError::Csv(err) => err.into(),
+            InboxError::Io(err) => err.into(),
+            err => Error::data(err.to_string()),
+        }
     }
 }
 
 impl From<SourceError> for Error {
     fn from(err: SourceError) -> Error {
-        Error(format!("{}", err))
+        match err {
+            SourceError::Csv(err) => err.into(),
+            SourceError::Io(err) => err.into(),
+            err => Error::data(err.to_string()),
+        }
     }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curator_sketch::history;
+
+    #[test]
+    fn csv_location() {
+        let csv = "date,url,title,summary,tags,origin\n2020-01-01,https://a.org\n";
+        let err =
+            Error::from(history::from_reader(csv.as_bytes()).unwrap_err()).in_file("history.csv");
+
+        assert_eq!(err.exit_code(), 65);
+        assert!(err.to_string().starts_with("history.csv:2: "));
+    }
+
+    #[test]
+    fn toml_location() {
+        let err = Error::from(toml::from_str::<toml::Value>("a = 1\nb = \n").unwrap_err());
+
+        assert_eq!(err.exit_code(), 65);
+        assert!(err.to_string().starts_with("<input>:2:"));
     }
 }
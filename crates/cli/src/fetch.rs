@@ -0,0 +1,52 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use std::fs;
+use std::io::Read;
+use std::time::Duration;
+
+/// Retrieves the content behind a URL.
+///
+/// Commands take a `Fetch` instead of talking to the network directly so
+/// they can be exercised against local files or stubs.
+pub trait Fetch {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// Fetches `http(s)://` URLs over the network and `file://` URLs from disk.
+pub struct HttpFetcher {
+    agent: ureq::Agent,
+}
+
+impl HttpFetcher {
+    pub fn new() -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("curator/", env!("CARGO_PKG_VERSION")))
+            .build();
+
+        HttpFetcher { agent }
+    }
+}
+
+impl Fetch for HttpFetcher {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        if let Some(path) = url.strip_prefix("file://") {
+            return fs::read(path).map_err(|err| Error::from(err).in_file(path));
+        }
+
+        let response = self
+            .agent
+            .get(url)
+            .call()
+            .map_err(|err| Error::unavailable(err.to_string()))?;
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
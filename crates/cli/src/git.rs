@@ -0,0 +1,194 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use crate::manifest::Manifest;
+use clap::Parser;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Commits the given store files when the cellar opted in with a `[git]`
+/// section.
+///
+/// Nothing is committed when the files have no changes. Stores that were
+/// never written, like an inbox nothing was added to, are left out.
+pub fn commit(manifest: &Manifest, paths: &[&Path], message: &str) -> Result<(), Error> {
+    let paths: Vec<&Path> = paths.iter().copied().filter(|p| p.exists()).collect();
+
+    if !manifest.auto_commit() || paths.is_empty() {
+        return Ok(());
+    }
+
+    let root = manifest.root();
+    let mut add = vec![OsStr::new("add"), OsStr::new("--")];
+    add.extend(paths.iter().map(|p| p.as_os_str()));
+    run(root, &add)?;
+
+    let mut staged = vec![
+        OsStr::new("diff"),
+        OsStr::new("--cached"),
+        OsStr::new("--quiet"),
+        OsStr::new("--"),
+    ];
+    staged.extend(paths.iter().map(|p| p.as_os_str()));
+    if git(root, &staged)?.status.success() {
+        return Ok(());
+    }
+
+    let mut commit = vec![
+        OsStr::new("commit"),
+        OsStr::new("--quiet"),
+        OsStr::new("-m"),
+        OsStr::new(message),
+        OsStr::new("--"),
+    ];
+    commit.extend(paths.iter().map(|p| p.as_os_str()));
+    run(root, &commit)?;
+
+    Ok(())
+}
+
+/// Turns the directory into a git repository unless it already is one.
+///
+/// Returns whether a repository was created.
+pub fn init(root: &Path) -> Result<bool, Error> {
+    let inside = git(root, &[OsStr::new("rev-parse"), OsStr::new("--git-dir")])?;
+
+    if inside.status.success() {
+        return Ok(false);
+    }
+
+    run(root, &[OsStr::new("init"), OsStr::new("--quiet")])?;
+
+    Ok(true)
+}
+
+/// Shortens a free text to fit a commit subject.
+pub fn subject(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("").trim();
+
+    if line.chars().count() > 50 {
+        format!("{}…", line.chars().take(49).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Log {
+    /// The number of changes to show.
+    #[clap(long, short = 'n', default_value = "10")]
+    number: usize,
+}
+
+impl Log {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        print!("{}", log(&manifest, self.number)?);
+
+        Ok(())
+    }
+}
+
+/// The last changes to the stores, one per line.
+fn log(manifest: &Manifest, number: usize) -> Result<String, Error> {
+    let number = format!("-n{}", number);
+    let mut args = vec![
+        OsStr::new("log"),
+        OsStr::new(&number),
+        OsStr::new("--date=short"),
+        OsStr::new("--format=%h  %ad  %s"),
+        OsStr::new("--"),
+    ];
+    let paths = manifest.store_paths();
+    args.extend(paths.iter().map(|p| p.as_os_str()));
+
+    let output = run(manifest.root(), &args)?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn git(root: &Path, args: &[&OsStr]) -> Result<Output, Error> {
+    Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|err| {
+            Error::unavailable(format!("Couldn't run git: {}", err))
+                .with_hint("Install git or disable [git] in the manifest")
+        })
+}
+
+fn run(root: &Path, args: &[&OsStr]) -> Result<Output, Error> {
+    let output = git(root, args)?;
+
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(Error::unavailable(format!(
+            "git {} failed: {}",
+            args[0].to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn commit_stores() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("Cellar.toml");
+        fs::write(
+            &path,
+            "version = 1\n[activity]\nhistory = \"history.csv\"\ninbox = \"inbox.csv\"\n[git]\n",
+        )?;
+        let manifest = Manifest::new(&path)?;
+        let root = manifest.root();
+        init(root)?;
+        run(
+            root,
+            &[
+                OsStr::new("config"),
+                OsStr::new("user.name"),
+                OsStr::new("Curator"),
+            ],
+        )?;
+        run(
+            root,
+            &[
+                OsStr::new("config"),
+                OsStr::new("user.email"),
+                OsStr::new("curator@localhost"),
+            ],
+        )?;
+
+        let history = manifest.history_path()?;
+        let inbox = manifest.inbox_path()?;
+        fs::write(history, "date,url\n")?;
+        commit(&manifest, &[history, inbox], "history: add A")?;
+        commit(&manifest, &[history, inbox], "history: nothing")?;
+        fs::write(history, "date,url\n2020-09-28,https://b.org\n")?;
+        commit(&manifest, &[history], "history: add B")?;
+
+        let log = log(&manifest, 10)?;
+        let subjects: Vec<&str> = log
+            .lines()
+            .map(|line| &line[line.len().min(21)..])
+            .collect();
+
+        assert!(!inbox.exists());
+        assert_eq!(subjects.len(), 2);
+        assert!(log.lines().next().unwrap().ends_with("history: add B"));
+        assert!(log.lines().nth(1).unwrap().ends_with("history: add A"));
+
+        Ok(())
+    }
+}
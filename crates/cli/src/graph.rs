@@ -0,0 +1,292 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Exports the records with their tags, sources, links and annotations as a
+//! graph.
+//!
+//! Nodes are identified by their kind and key, e.g. `record:<url>`,
+//! `tag:<name>`, `source:<id>` or `annotation:<url>#<n>`, the nth annotation
+//! of the record. Links to URLs missing from the history and annotations of
+//! records no longer in it are left out.
+
+use crate::error::Error;
+use crate::history;
+use crate::manifest::Manifest;
+use crate::store;
+use clap::Parser;
+use curator_sketch::annotation::{self, Annotation};
+use curator_sketch::history::Record;
+use curator_sketch::source::{self, Source};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Parser)]
+pub struct Graph {
+    #[clap(long, short = 'f', default_value = "dot", value_parser = ["dot", "graphml", "json"])]
+    format: String,
+}
+
+impl Graph {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let records = history::load(manifest.history_path()?)?;
+        let sources = match manifest.sources_path() {
+            Ok(path) => store::read_optional(path, source::from_reader)?,
+            Err(_) => Vec::new(),
+        };
+        let annotations = history::load_annotations(&manifest)?;
+        let graph = Network::new(&records, &sources, &annotations);
+
+        match &self.format[..] {
+            "graphml" => print!("{}", graph.to_graphml()),
+            "json" => {
+                let json = serde_json::to_string_pretty(&graph)
+                    .map_err(|err| Error::from(err.to_string()))?;
+                println!("{}", json);
+            }
+            _ => print!("{}", graph.to_dot()),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Node {
+    id: String,
+    /// Either `record`, `tag`, `source` or `annotation`.
+    kind: &'static str,
+    label: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Edge {
+    source: String,
+    target: String,
+    /// Either `tagged`, `origin`, `link` or `annotated`.
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Network {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Network {
+    fn new(records: &[Record], sources: &[Source], annotations: &[Annotation]) -> Self {
+        let mut network = Network::default();
+        let urls: BTreeSet<&str> = records.iter().map(|record| record.url()).collect();
+        let mut tags = BTreeSet::new();
+        let mut origins = BTreeSet::new();
+
+        for record in records {
+            let id = format!("record:{}", record.url());
+            network.nodes.push(Node {
+                id: id.clone(),
+                kind: "record",
+                label: record.title().to_string(),
+            });
+
+            for tag in record.tags() {
+                network.edge(&id, format!("tag:{}", tag), "tagged", None);
+                tags.insert(tag);
+            }
+
+            if let Some(origin) = record.origin() {
+                network.edge(&id, format!("source:{}", origin), "origin", None);
+                origins.insert(origin);
+            }
+
+            for link in record.related() {
+                if urls.contains(link.url()) {
+                    let target = format!("record:{}", link.url());
+                    network.edge(&id, target, "link", link.label().map(String::from));
+                }
+            }
+
+            for (n, kept) in annotation::of(annotations, record.url()).enumerate() {
+                let target = format!("annotation:{}#{}", record.url(), n + 1);
+                network.nodes.push(Node {
+                    id: target.clone(),
+                    kind: "annotation",
+                    label: kept.quote().to_string(),
+                });
+                network.edge(&id, target, "annotated", kept.note().map(String::from));
+            }
+        }
+
+        for tag in tags {
+            network.nodes.push(Node {
+                id: format!("tag:{}", tag),
+                kind: "tag",
+                label: tag,
+            });
+        }
+
+        // Sources nothing came from are kept, as well as origins missing
+        // from the source store.
+        let known: BTreeSet<&str> = sources.iter().map(|src| src.id()).collect();
+        let unknown = origins.iter().filter(|id| !known.contains(id.as_str()));
+
+        for id in known.iter().copied().chain(unknown.map(String::as_str)) {
+            network.nodes.push(Node {
+                id: format!("source:{}", id),
+                kind: "source",
+                label: id.to_string(),
+            });
+        }
+
+        network
+    }
+
+    fn edge(&mut self, source: &str, target: String, kind: &'static str, label: Option<String>) {
+        self.edges.push(Edge {
+            source: source.to_string(),
+            target,
+            kind,
+            label,
+        });
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph curator {\n");
+
+        for node in &self.nodes {
+            let shape = match node.kind {
+                "record" => "box",
+                "tag" => "ellipse",
+                "annotation" => "note",
+                _ => "diamond",
+            };
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\", shape={}];\n",
+                dot_escape(&node.id),
+                dot_escape(&node.label),
+                shape
+            ));
+        }
+
+        for edge in &self.edges {
+            let label = edge.label.as_deref().unwrap_or(edge.kind);
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                dot_escape(&edge.source),
+                dot_escape(&edge.target),
+                dot_escape(label)
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_graphml(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kind\" for=\"all\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"label\" for=\"all\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <graph id=\"curator\" edgedefault=\"directed\">\n",
+        ));
+
+        for node in &self.nodes {
+            xml.push_str(&format!(
+                "    <node id=\"{}\"><data key=\"kind\">{}</data><data key=\"label\">{}</data></node>\n",
+                xml_escape(&node.id),
+                node.kind,
+                xml_escape(&node.label)
+            ));
+        }
+
+        for edge in &self.edges {
+            let label = match &edge.label {
+                Some(label) => format!("<data key=\"label\">{}</data>", xml_escape(label)),
+                None => String::new(),
+            };
+            xml.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kind\">{}</data>{}</edge>\n",
+                xml_escape(&edge.source),
+                xml_escape(&edge.target),
+                edge.kind,
+                label
+            ));
+        }
+
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curator_sketch::history::Link;
+
+    #[test]
+    fn export_formats() {
+        let mut a = Record::new("https://a.org")
+            .with_title("A \"quoted\" <title>")
+            .with_summary("")
+            .with_tags(&["rust"])
+            .with_origin("blog")
+            .build()
+            .unwrap();
+        a.link(Link::new("https://b.org", Some("response to")).unwrap());
+        a.link(Link::new("https://gone.org", None).unwrap());
+        let b = Record::new("https://b.org")
+            .with_title("B")
+            .with_summary("")
+            .with_tags(&["rust"])
+            .build()
+            .unwrap();
+        let sources = vec![Source::new("feed", "rss", "https://f.org", "")];
+        let annotations = vec![
+            Annotation::new("https://a.org", "A passage")
+                .unwrap()
+                .with_note("Why"),
+            Annotation::new("https://gone.org", "Orphan").unwrap(),
+        ];
+        let network = Network::new(&[a, b], &sources, &annotations);
+
+        let ids: Vec<&str> = network.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "record:https://a.org",
+                "annotation:https://a.org#1",
+                "record:https://b.org",
+                "tag:rust",
+                "source:feed",
+                "source:blog"
+            ]
+        );
+        assert_eq!(network.edges.len(), 5);
+
+        let dot = network.to_dot();
+        assert!(dot.contains(r#"[label="A \"quoted\" <title>", shape=box]"#));
+        assert!(dot
+            .contains(r#""record:https://a.org" -> "record:https://b.org" [label="response to"]"#));
+        assert!(dot.contains(r#"[label="A passage", shape=note]"#));
+
+        let graphml = network.to_graphml();
+        assert!(graphml.contains("A &quot;quoted&quot; &lt;title&gt;"));
+        assert!(!graphml.contains("gone.org"));
+    }
+}
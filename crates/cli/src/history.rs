@@ -4,15 +4,33 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
-use crate::error::Error;
+use crate::archive as archiver;
+use crate::browser;
+use crate::config::Settings;
+use crate::error::{Error, ErrorKind};
+use crate::fetch::{Fetch, HttpFetcher};
+use crate::git;
+use crate::journal::Snapshot;
 use crate::manifest::Manifest;
+use crate::readable;
+use crate::store;
+use crate::tags;
 use clap::Parser;
-use curator_sketch::history::{Record, RecordBuilder};
+use curator_sketch::annotation::{self, Annotation};
+use curator_sketch::archive::{self, Entry};
+use curator_sketch::history::{self, ReadingStatus, Record, RecordBuilder};
+use curator_sketch::import::Service;
+use curator_sketch::related;
 use curator_sketch::source::{self, Source};
-use dialoguer::Editor;
+use curator_sketch::suggest::Model;
+use curator_sketch::text::{self, Text};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use skim::prelude::*;
+use std::collections::HashSet;
 use std::fs;
-use std::io::Cursor;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Parser)]
 pub struct Cmd {
@@ -24,8 +42,37 @@ pub struct Cmd {
 pub enum Subcommand {
     /// Adds a resource to the history store.
     Add(Add),
+    /// Imports the bookmarks exported from a read-later service.
+    Import(Import),
+    /// Copies a record into another registered cellar.
+    Copy(Copy),
+    /// Saves the pages of the records offline.
+    Archive(Archive),
+    /// Opens a record in the browser.
+    Open(Open),
+    /// Extracts the readable text of the record pages.
+    Extract(Extract),
+    /// Lists the records, optionally filtered.
+    List(List),
+    /// Shows a record.
+    Show(Show),
+    /// Lists the records on the same topic as another one.
+    Related(Related),
+    /// Links a record to another one.
+    Link(Link),
+    /// Removes the link from a record to another one.
+    Unlink(Unlink),
+    /// Keeps a passage of a record with an optional note.
+    Annotate(Annotate),
+    /// Sets whether a record was read.
+    Status(Status),
+    /// Rates a record from 1 to 5.
+    Rate(Rate),
 }
 
+/// The number of related records suggested by default.
+const RELATED: usize = 5;
+
 #[derive(Debug, Parser)]
 pub struct Add {
     /// The URL to store.
@@ -34,21 +81,946 @@ pub struct Add {
 
 impl Add {
     pub fn run(&mut self, manifest: Manifest) -> Result<(), Error> {
-        let source_rdr = fs::File::open(manifest.sources_path())?;
-        let sources: Vec<Source> = source::from_reader(source_rdr)?;
-        let record = prompt_record(&self.url, &sources)?;
+        let sources: Vec<Source> = store::read(manifest.sources_path()?, source::from_reader)?;
+        let records = load(manifest.history_path()?)?;
+        let model = tags::model(&manifest, &records)?;
+        let builder = manifest.settings().draft(Record::new(self.url.as_str()));
+        let record = prompt_record(builder, &sources, &records, &model, manifest.settings())?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[manifest.history_path()?])?;
+
+        record.append_into(manifest.history_path()?)?;
+
+        snapshot.commit(
+            &manifest,
+            &format!("history: add {}", git::subject(record.title())),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Import {
+    /// The service that produced the export file.
+    #[clap(long, short = 'f', value_parser = ["pocket", "pinboard", "raindrop", "instapaper"])]
+    format: String,
+    /// The export file to read.
+    file: PathBuf,
+}
+
+impl Import {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let service: Service = self.format.parse()?;
+        let builders = store::read(&self.file, |file| service.read(file))?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[manifest.history_path()?, manifest.sources_path()?])?;
+
+        let sources: Vec<Source> = store::read(manifest.sources_path()?, source::from_reader)?;
+        if !sources.iter().any(|src| src.id() == service.id()) {
+            service.source().append_into(manifest.sources_path()?)?;
+        }
+
+        // Saved as a whole so a store written with older headers is upgraded
+        // rather than appended rows it can't read.
+        let mut records = store::read(manifest.history_path()?, history::from_reader)?;
+        let mut seen: HashSet<String> = records
+            .iter()
+            .map(|record| record.url().to_string())
+            .collect();
+        let mut imported = 0;
+        let mut skipped = 0;
+        let mut failed = 0;
+
+        for (idx, builder) in builders.into_iter().enumerate() {
+            if seen.contains(builder.url()) {
+                skipped += 1;
+                continue;
+            }
+
+            let url = builder.url().to_string();
+            match builder.build() {
+                Ok(record) => {
+                    records.push(record);
+                    seen.insert(url);
+                    imported += 1;
+                }
+                Err(err) => {
+                    eprintln!("Entry {} ({}): {}", idx + 1, url, err);
+                    failed += 1;
+                }
+            }
+        }
+
+        save(manifest.history_path()?, &records)?;
+
+        println!(
+            "Imported {} records from {}, skipped {} already in the history, {} failed",
+            imported,
+            service.id(),
+            skipped,
+            failed
+        );
+
+        snapshot.commit(
+            &manifest,
+            &format!("history: import {} records from {}", imported, service.id()),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Copy {
+    /// The URL of the record to copy.
+    url: String,
+    /// The name of the cellar to copy the record into.
+    #[clap(long, value_name = "name")]
+    to: String,
+    /// Removes the record from the current cellar once copied.
+    #[clap(long = "move")]
+    remove: bool,
+}
+
+impl Copy {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let target =
+            Manifest::discover(None, Some(&self.to))?.with_lock_timeout(manifest.lock_timeout());
+        let history_path = manifest.history_path()?;
+        let target_history_path = target.history_path()?;
+
+        if history_path == target_history_path {
+            return Err(Error::usage(format!(
+                "The record is already in the '{}' cellar",
+                self.to
+            )));
+        }
+
+        let _lock = manifest.lock()?;
+        let _target_lock = target.lock()?;
+        let mut records = load(history_path)?;
+        let idx = records
+            .iter()
+            .position(|record| record.url() == self.url)
+            .ok_or_else(|| {
+                Error::usage(format!("No record for {} in the history", self.url))
+                    .with_hint(format!("Check the URL in {}", history_path.display()))
+            })?;
+        let record = records[idx].clone();
+
+        if load(target_history_path)?
+            .iter()
+            .any(|x| x.url() == record.url())
+        {
+            println!("Already in the '{}' cellar", self.to);
+        } else {
+            let target_sources_path = target.sources_path()?;
+            let snapshot = Snapshot::take(&[target_history_path, target_sources_path])?;
+
+            if let Some(origin) = record.origin() {
+                let target_sources = store::read(target_sources_path, source::from_reader)?;
+
+                if !target_sources.iter().any(|src| src.id() == origin) {
+                    let sources = store::read(manifest.sources_path()?, source::from_reader)?;
+
+                    if let Some(src) = sources.iter().find(|src| src.id() == origin) {
+                        src.append_into(target_sources_path)?;
+                    }
+                }
+            }
+
+            record.append_into(target_history_path)?;
+            snapshot.commit(
+                &target,
+                &format!("history: copy {}", git::subject(record.title())),
+            )?;
+            println!("Copied to the '{}' cellar", self.to);
+        }
+
+        if self.remove {
+            let snapshot = Snapshot::take(&[history_path])?;
+            records.remove(idx);
+            save(history_path, &records)?;
+            snapshot.commit(
+                &manifest,
+                &format!(
+                    "history: move {} to {}",
+                    git::subject(record.title()),
+                    self.to
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Archive {
+    /// The URL of the record to archive.
+    #[clap(required_unless_present = "all")]
+    url: Option<String>,
+    /// Archives every record without an archived copy.
+    #[clap(long, conflicts_with = "url")]
+    all: bool,
+}
+
+impl Archive {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let config = manifest.archive()?;
+        let index_path = config.index_path();
+        let history_path = manifest.history_path()?;
+        let records = load(history_path)?;
+        let index = store::read_optional(&index_path, archive::from_reader)?;
+        let pending: Vec<&Record> = match &self.url {
+            Some(url) => vec![find(&records, url, history_path)?],
+            None => records
+                .iter()
+                .filter(|record| archive::latest(&index, record.url()).is_none())
+                .collect(),
+        };
+        let fetcher = HttpFetcher::new();
+        let format = config.format().to_string();
+        let mut entries = Vec::new();
+        let mut failed = 0;
+
+        for record in pending {
+            match archiver::save(&fetcher, record.url(), config.dir(), config.format()) {
+                Ok(saved) => {
+                    for (asset, err) in &saved.missing {
+                        eprintln!("{}: {}", asset, err);
+                    }
+                    entries.push(Entry::new(record.url(), &saved.hash, &format));
+                }
+                Err(err) if self.url.is_some() => return Err(err),
+                Err(err) => {
+                    eprintln!("{}: {}", record.url(), err);
+                    failed += 1;
+                }
+            }
+        }
+
+        println!("Archived {} records, {} failed", entries.len(), failed);
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        // Fetching can be slow, so the lock is only taken to add the new
+        // copies to the index as it is now.
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[&index_path])?;
+        let mut index = store::read_optional(&index_path, archive::from_reader)?;
+        let message = match &entries[..] {
+            [entry] => format!("history: archive {}", entry.url()),
+            _ => format!("history: archive {} records", entries.len()),
+        };
+        index.extend(entries);
+        store::replace(&index_path, |file| Ok(archive::to_writer(&index, file)?))?;
+
+        snapshot.commit(&manifest, &message)
+    }
+}
+
+/// The directory of the archive holding the pages extracted from WARC files.
+const EXTRACTED_DIR: &str = "extracted";
+
+#[derive(Debug, Parser)]
+pub struct Open {
+    /// The URL of the record to open.
+    url: String,
+    /// Opens the latest archived copy instead of the live page.
+    #[clap(long)]
+    archived: bool,
+}
+
+impl Open {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let history_path = manifest.history_path()?;
+        let record = find(&load(history_path)?, &self.url, history_path)?.clone();
+
+        if !self.archived {
+            return browser::open(record.url());
+        }
+
+        let config = manifest.archive()?;
+        let index = store::read_optional(&config.index_path(), archive::from_reader)?;
+        let entry = archive::latest(&index, record.url()).ok_or_else(|| {
+            Error::usage(format!("No archived copy of {}", record.url()))
+                .with_hint(format!("Run 'curator history archive {}'", record.url()))
+        })?;
+        let mut path = config.dir().join(entry.file_name());
+
+        if entry.format() == "warc" {
+            // Browsers can't read WARC files, so the page is extracted next
+            // to the archive rather than to a shared temporary directory.
+            let dir = config.dir().join(EXTRACTED_DIR);
+            let extracted = dir.join(format!("{}.html", entry.hash()));
+            let page = archived_page(config.dir(), entry)?;
+            fs::create_dir_all(&dir).map_err(|err| Error::from(err).in_file(&dir))?;
+            store::replace(&extracted, |mut file| Ok(file.write_all(&page)?))?;
+            path = extracted;
+        }
+
+        browser::open(&path.to_string_lossy())
+    }
+}
+
+/// The HTML of an archived copy.
+fn archived_page(dir: &Path, entry: &Entry) -> Result<Vec<u8>, Error> {
+    let path = dir.join(entry.file_name());
+    let bytes = fs::read(&path).map_err(|err| Error::from(err).in_file(&path))?;
+
+    if entry.format() != "warc" {
+        return Ok(bytes);
+    }
+
+    archiver::warc_page(&bytes)
+        .map(|page| page.to_vec())
+        .ok_or_else(|| Error::data("No page in the WARC file").in_file(&path))
+}
+
+#[derive(Debug, Parser)]
+pub struct Extract {
+    /// The URL of the record to extract the text of.
+    #[clap(required_unless_present = "all")]
+    url: Option<String>,
+    /// Extracts the text of every record without one.
+    #[clap(long, conflicts_with = "url")]
+    all: bool,
+}
+
+impl Extract {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let texts_path = manifest.texts_path()?;
+        let history_path = manifest.history_path()?;
+        let records = load(history_path)?;
+        let texts = store::read_optional(texts_path, text::from_reader)?;
+        let pending: Vec<&Record> = match &self.url {
+            Some(url) => vec![find(&records, url, history_path)?],
+            None => records
+                .iter()
+                .filter(|record| text::find(&texts, record.url()).is_none())
+                .collect(),
+        };
+        let archive = match manifest.archive() {
+            Ok(config) => Some((
+                config.dir(),
+                store::read_optional(&config.index_path(), archive::from_reader)?,
+            )),
+            Err(_) => None,
+        };
+        let fetcher = HttpFetcher::new();
+        let mut extracted = Vec::new();
+        let mut failed = 0;
+
+        for record in pending {
+            // The archived copy is preferred, as the page may have changed
+            // or gone since.
+            let page = match &archive {
+                Some((dir, index)) => match archive::latest(index, record.url()) {
+                    Some(entry) => archived_page(dir, entry),
+                    None => fetcher.fetch(record.url()),
+                },
+                None => fetcher.fetch(record.url()),
+            };
+
+            match page {
+                Ok(page) => {
+                    let body = readable::extract(&String::from_utf8_lossy(&page));
+                    extracted.push(Text::new(record.url(), &body));
+                }
+                Err(err) if self.url.is_some() => return Err(err),
+                Err(err) => {
+                    eprintln!("{}: {}", record.url(), err);
+                    failed += 1;
+                }
+            }
+        }
+
+        println!("Extracted {} records, {} failed", extracted.len(), failed);
+
+        if extracted.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[texts_path])?;
+        let mut texts = store::read_optional(texts_path, text::from_reader)?;
+        let message = match &extracted[..] {
+            [text] => format!("history: extract {}", text.url()),
+            _ => format!("history: extract {} records", extracted.len()),
+        };
+        for text in extracted {
+            text::upsert(&mut texts, text);
+        }
+        store::replace(texts_path, |file| Ok(text::to_writer(&texts, file)?))?;
+
+        snapshot.commit(&manifest, &message)
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Related {
+    /// The URL of the record to find related records for.
+    url: String,
+    /// The maximum number of records to list.
+    #[clap(long, short = 'n', default_value_t = RELATED)]
+    limit: usize,
+}
+
+impl Related {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let history_path = manifest.history_path()?;
+        let records = load(history_path)?;
+        let record = find(&records, &self.url, history_path)?;
+
+        for (related, score) in related::Related::new(&records).to_record(record, self.limit) {
+            println!("{:.2}  {}  {}", score, related.url(), related.title());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Link {
+    /// The URL of the record to link from.
+    from: String,
+    /// The URL of the record to link to.
+    to: String,
+    /// How the records relate, e.g. "response to" or "part of".
+    #[clap(long, short = 'l')]
+    label: Option<String>,
+}
+
+impl Link {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.history_path()?;
+        let link = history::Link::new(&self.to, self.label.as_deref())?;
+
+        if self.from == self.to {
+            return Err(Error::usage("A record can't link to itself"));
+        }
+
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut records = load(path)?;
+        find(&records, &self.to, path)?;
+        let idx = position(&records, &self.from, path)?;
+        records[idx].link(link);
+        save(path, &records)?;
+
+        snapshot.commit(
+            &manifest,
+            &format!(
+                "history: link {} to {}",
+                git::subject(records[idx].title()),
+                self.to
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Unlink {
+    /// The URL of the record to unlink from.
+    from: String,
+    /// The URL of the linked record.
+    to: String,
+}
+
+impl Unlink {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.history_path()?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut records = load(path)?;
+        let idx = position(&records, &self.from, path)?;
 
-        record.append_into(manifest.history_path())?;
+        if !records[idx].unlink(&self.to) {
+            return Err(Error::usage(format!(
+                "{} doesn't link to {}",
+                self.from, self.to
+            )));
+        }
+
+        save(path, &records)?;
+
+        snapshot.commit(
+            &manifest,
+            &format!(
+                "history: unlink {} from {}",
+                git::subject(records[idx].title()),
+                self.to
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Status {
+    /// The URL of the record.
+    url: String,
+    #[clap(value_parser = ["unread", "reading", "read", "abandoned"])]
+    status: String,
+}
+
+impl Status {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.history_path()?;
+        let status: ReadingStatus = self.status.parse()?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut records = load(path)?;
+        let idx = position(&records, &self.url, path)?;
+        records[idx].set_status(Some(status));
+        save(path, &records)?;
+
+        snapshot.commit(
+            &manifest,
+            &format!(
+                "history: mark {} as {}",
+                git::subject(records[idx].title()),
+                status
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Rate {
+    /// The URL of the record.
+    url: String,
+    #[clap(value_parser = clap::value_parser!(u8).range(1..=history::MAX_RATING as i64))]
+    rating: u8,
+}
+
+impl Rate {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.history_path()?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut records = load(path)?;
+        let idx = position(&records, &self.url, path)?;
+        records[idx].set_rating(Some(self.rating))?;
+        save(path, &records)?;
+
+        snapshot.commit(
+            &manifest,
+            &format!(
+                "history: rate {} {}",
+                git::subject(records[idx].title()),
+                self.rating
+            ),
+        )
+    }
+}
+
+/// The annotation template edited by the user.
+const ANNOTATION: &str = r#"# Keep a passage of "{title}"
+# Use triple quotes for a passage over several lines.
+quote = """
+"""
+note = ""
+location = ""
+"#;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnnotationDraft {
+    quote: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    location: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct Annotate {
+    /// The URL of the record to annotate.
+    url: String,
+}
+
+impl Annotate {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.annotations_path()?;
+        let history_path = manifest.history_path()?;
+        let records = load(history_path)?;
+        let record = find(&records, &self.url, history_path)?;
+        let settings = manifest.settings();
+        let template = ANNOTATION.replace("{title}", &record.title().replace('\n', " "));
+        let value = settings
+            .editor()
+            .extension(".toml")
+            .edit(&template)?
+            .ok_or_else(Error::aborted)?;
+        let draft: AnnotationDraft =
+            toml::from_str(&value).map_err(|err| Error::from(err).with_kind(ErrorKind::Usage))?;
+        let annotation = Annotation::new(record.url(), &draft.quote)?
+            .with_date(settings.today())
+            .with_note(&draft.note)
+            .with_location(&draft.location);
+
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut annotations = store::read_optional(path, annotation::from_reader)?;
+        annotations.push(annotation);
+        store::replace(path, |file| Ok(annotation::to_writer(&annotations, file)?))?;
+
+        snapshot.commit(
+            &manifest,
+            &format!("history: annotate {}", git::subject(record.title())),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Show {
+    /// The URL of the record to show.
+    url: String,
+    /// Prints the extracted text of the page too.
+    #[clap(long)]
+    text: bool,
+}
+
+impl Show {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let history_path = manifest.history_path()?;
+        let records = load(history_path)?;
+        let record = find(&records, &self.url, history_path)?;
+        let texts = if self.text {
+            store::read_optional(manifest.texts_path()?, text::from_reader)?
+        } else {
+            load_texts(&manifest)?
+        };
+        let found = text::find(&texts, record.url());
+
+        if self.text && found.is_none() {
+            return Err(
+                Error::usage(format!("No text extracted for {}", record.url()))
+                    .with_hint(format!("Run 'curator history extract {}'", record.url())),
+            );
+        }
+
+        println!("{}", record.title());
+        println!("{}", record.url());
+        println!("Date: {}", record.date());
+        println!("Tags: {}", record.tags().join(", "));
+        if let Some(origin) = record.origin() {
+            println!("Origin: {}", origin);
+        }
+        if let Some(status) = record.status() {
+            println!("Status: {}", status);
+        }
+        if let Some(rating) = record.rating() {
+            println!("Rating: {}/{}", rating, history::MAX_RATING);
+        }
+        if let Some(found) = found {
+            println!(
+                "Reading time: {} min ({} words)",
+                found.minutes(),
+                found.words()
+            );
+        }
+        println!("\n{}", record.summary());
+
+        if !record.related().is_empty() {
+            println!("\nLinks:");
+            for link in record.related() {
+                println!("  {}", link);
+            }
+        }
+
+        let annotations = load_annotations(&manifest)?;
+        let mut kept = annotation::of(&annotations, record.url()).peekable();
+        if kept.peek().is_some() {
+            println!("\nAnnotations:");
+            for annotation in kept {
+                for line in annotation.quote().lines() {
+                    println!("  > {}", line);
+                }
+                if let Some(location) = annotation.location() {
+                    println!("  ({})", location);
+                }
+                if let Some(note) = annotation.note() {
+                    println!("  {}", note);
+                }
+                println!();
+            }
+        }
+
+        let related = related::Related::new(&records).to_record(record, RELATED);
+        if !related.is_empty() {
+            println!("\nRelated:");
+            for (related, _) in related {
+                println!("  {}  {}", related.url(), related.title());
+            }
+        }
+
+        if let (true, Some(found)) = (self.text, found) {
+            println!("\n{}", found.text());
+        }
 
         Ok(())
     }
 }
 
-fn prompt_record(url: &str, sources: &[Source]) -> Result<Record, Error> {
-    let builder = Record::new(url);
-    let template = toml::to_string(&builder)?;
-    let record = if let Some(value) = Editor::new().extension(".toml").edit(&template)? {
-        let mut entry: RecordBuilder = toml::from_str(&value)?;
+#[derive(Debug, Parser)]
+pub struct List {
+    /// Only lists the records with this reading status.
+    #[clap(long, short = 's', value_parser = ["unread", "reading", "read", "abandoned"])]
+    status: Option<String>,
+    /// Only lists the records rated at least this.
+    #[clap(long, short = 'r', value_parser = clap::value_parser!(u8).range(1..=history::MAX_RATING as i64))]
+    min_rating: Option<u8>,
+    /// Only lists the records with this tag.
+    #[clap(long, short = 't')]
+    tag: Option<String>,
+    /// Only lists the records from the source with this id.
+    #[clap(long, short = 'o')]
+    origin: Option<String>,
+    /// Only lists the records containing this text, including their
+    /// extracted text and annotations.
+    #[clap(long, short = 'q')]
+    query: Option<String>,
+    /// `csv` writes the records as in the history store, `json` adds their
+    /// annotations, word count and reading time.
+    #[clap(long, short = 'f', default_value = "term", value_parser = ["term", "csv", "json"])]
+    format: String,
+    /// Adds the extracted text of the records to the `json` format.
+    #[clap(long)]
+    text: bool,
+}
+
+impl List {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let status = self
+            .status
+            .as_deref()
+            .map(str::parse::<ReadingStatus>)
+            .transpose()?;
+        let records = load(manifest.history_path()?)?;
+        let texts = load_texts(&manifest)?;
+        let annotations = load_annotations(&manifest)?;
+        let query = self.query.as_deref().map(str::to_lowercase);
+        let listed: Vec<Record> = records
+            .into_iter()
+            .filter(|record| status.is_none() || record.status() == status)
+            .filter(|record| match self.min_rating {
+                Some(min) => record.rating().is_some_and(|rating| rating >= min),
+                None => true,
+            })
+            .filter(|record| match &self.tag {
+                Some(tag) => record.tags().contains(tag),
+                None => true,
+            })
+            .filter(|record| self.origin.is_none() || record.origin() == self.origin)
+            .filter(|record| match &query {
+                Some(query) => searched(record, &texts, &annotations)
+                    .iter()
+                    .any(|text| text.to_lowercase().contains(query)),
+                None => true,
+            })
+            .collect();
+
+        match &self.format[..] {
+            "csv" => {
+                let stdout = std::io::stdout();
+                history::to_writer(&listed, stdout.lock())?;
+                return Ok(());
+            }
+            "json" => {
+                let listed: Vec<Value> = listed
+                    .iter()
+                    .map(|record| {
+                        let mut value =
+                            with_details(record_json(record), &texts, &annotations, record.url());
+                        match text::find(&texts, record.url()) {
+                            Some(found) if self.text => value["text"] = json!(found.text()),
+                            _ => (),
+                        }
+                        value
+                    })
+                    .collect();
+                let json = serde_json::to_string_pretty(&listed)
+                    .map_err(|err| Error::from(err.to_string()))?;
+                println!("{}", json);
+                return Ok(());
+            }
+            _ => (),
+        }
+
+        for record in &listed {
+            println!(
+                "{:10}  {:9}  {:1}  {}  {}",
+                record.date(),
+                record.status().map(|s| s.to_string()).unwrap_or_default(),
+                record.rating().map(|r| r.to_string()).unwrap_or_default(),
+                record.url(),
+                record.title()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The record for the URL or an error pointing at the store.
+fn find<'a>(records: &'a [Record], url: &str, path: &Path) -> Result<&'a Record, Error> {
+    position(records, url, path).map(|idx| &records[idx])
+}
+
+/// The index of the record for the URL or an error pointing at the store.
+fn position(records: &[Record], url: &str, path: &Path) -> Result<usize, Error> {
+    records
+        .iter()
+        .position(|record| record.url() == url)
+        .ok_or_else(|| {
+            Error::usage(format!("No record for {} in the history", url))
+                .with_hint(format!("Check the URL in {}", path.display()))
+        })
+}
+
+/// Reads the history store, treating a missing file as an empty store.
+pub fn load(path: &Path) -> Result<Vec<Record>, Error> {
+    store::read_optional(path, history::from_reader)
+}
+
+/// Reads the extracted texts, none when the text store is disabled.
+pub fn load_texts(manifest: &Manifest) -> Result<Vec<Text>, Error> {
+    match manifest.texts_path() {
+        Ok(path) => store::read_optional(path, text::from_reader),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// Reads the annotations, none when the annotation store is disabled.
+pub fn load_annotations(manifest: &Manifest) -> Result<Vec<Annotation>, Error> {
+    match manifest.annotations_path() {
+        Ok(path) => store::read_optional(path, annotation::from_reader),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// The text searched for a record: its own fields, its extracted text and
+/// the quotes and notes of its annotations.
+pub fn searched<'a>(
+    record: &'a Record,
+    texts: &'a [Text],
+    annotations: &'a [Annotation],
+) -> Vec<&'a str> {
+    let body = text::find(texts, record.url()).map_or("", |t| t.text());
+    let mut searched = vec![record.url(), record.title(), record.summary(), body];
+
+    for kept in annotation::of(annotations, record.url()) {
+        searched.push(kept.quote());
+        searched.extend(kept.note());
+    }
+
+    searched
+}
+
+pub fn record_json(record: &Record) -> Value {
+    json!({
+        "url": record.url(),
+        "date": record.date().to_string(),
+        "title": record.title(),
+        "summary": record.summary(),
+        "tags": record.tags(),
+        "origin": record.origin(),
+        "related": record.related(),
+        "status": record.status(),
+        "rating": record.rating(),
+    })
+}
+
+/// Adds the annotations of the record and its word count and reading time,
+/// when extracted.
+pub fn with_details(
+    mut value: Value,
+    texts: &[Text],
+    annotations: &[Annotation],
+    url: &str,
+) -> Value {
+    if let Some(found) = text::find(texts, url) {
+        value["words"] = json!(found.words());
+        value["minutes"] = json!(found.minutes());
+    }
+
+    let kept: Vec<Value> = annotation::of(annotations, url)
+        .map(|kept| {
+            json!({
+                "date": kept.date().to_string(),
+                "quote": kept.quote(),
+                "note": kept.note(),
+                "location": kept.location(),
+            })
+        })
+        .collect();
+    value["annotations"] = json!(kept);
+
+    value
+}
+
+pub fn save(path: &Path, records: &[Record]) -> Result<(), Error> {
+    store::replace(path, |file| Ok(history::to_writer(records, file)?))
+}
+
+/// Opens the editor with the given draft and, if no origin is set, lets the
+/// user pick one of the sources.
+///
+/// The tags suggested by the model are offered as a commented out `tags`
+/// line, and the records in `history` related to the draft are listed as
+/// comments below it.
+pub fn prompt_record(
+    builder: RecordBuilder,
+    sources: &[Source],
+    history: &[Record],
+    model: &Model,
+    settings: &Settings,
+) -> Result<Record, Error> {
+    let mut template = String::new();
+    let existing = builder.tags();
+    let suggested: Vec<String> = model
+        .suggest(
+            builder.url(),
+            &builder.title().unwrap_or_default(),
+            &builder.summary().unwrap_or_default(),
+            tags::SUGGESTED + existing.len(),
+        )
+        .into_iter()
+        .map(|(tag, _)| tag)
+        .filter(|tag| !existing.contains(tag))
+        .take(tags::SUGGESTED)
+        .collect();
+
+    for line in toml::to_string(&builder)?.lines() {
+        template.push_str(line);
+        template.push('\n');
+
+        if line.starts_with("tags = ") && !suggested.is_empty() {
+            let all: Vec<String> = existing.iter().chain(&suggested).cloned().collect();
+            template.push_str("# Suggested, uncomment to use instead:\n");
+            template.push_str(&format!("# tags = {}\n", toml::Value::try_from(all)?));
+        }
+    }
+
+    let related = related::Related::new(history).to(
+        builder.url(),
+        &builder.title().unwrap_or_default(),
+        &builder.summary().unwrap_or_default(),
+        &existing,
+        RELATED,
+    );
+
+    if !related.is_empty() {
+        template.push_str("\n# Related records in the history:\n");
+        for (record, _) in related {
+            template.push_str(&format!("# - {} <{}>\n", record.title(), record.url()));
+        }
+    }
+
+    let record = if let Some(value) = settings.editor().extension(".toml").edit(&template)? {
+        let mut entry: RecordBuilder =
+            toml::from_str(&value).map_err(|err| Error::from(err).with_kind(ErrorKind::Usage))?;
 
         if entry.origin().is_none() {
             let sources = sources
@@ -61,17 +1033,77 @@ fn prompt_record(url: &str, sources: &[Source]) -> Result<Record, Error> {
             let items = item_reader.of_bufread(Cursor::new(sources));
             let selected_items = Skim::run_with(&options, Some(items))
                 .map(|out| out.selected_items)
-                .unwrap_or_else(|| Vec::new());
+                .unwrap_or_default();
 
             if let Some(item) = selected_items.first() {
-                entry.with_origin(item.output());
+                entry = entry.with_origin(item.output());
             }
         }
 
         entry.build()?
     } else {
-        return Err(Error::new("Aborted"));
+        return Err(Error::aborted());
     };
 
     Ok(record)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_into_legacy_store() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::write(
+            root.join("Cellar.toml"),
+            "version = 1\n[activity]\nhistory = \"history.csv\"\nsources = \"sources.csv\"\n",
+        )?;
+        fs::write(
+            root.join("history.csv"),
+            "date,url,title,summary,tags,origin\n2020-09-27,https://a.org,A,Asum,a,\n",
+        )?;
+        fs::write(root.join("sources.csv"), "id,type,url,comment\n")?;
+        fs::write(
+            root.join("pinboard.json"),
+            r#"[{"href":"https://b.org","description":"B","extended":"Bsum","time":"2020-09-28T10:00:00Z","tags":"a"}]"#,
+        )?;
+
+        let import = Import {
+            format: "pinboard".to_string(),
+            file: root.join("pinboard.json"),
+        };
+        import.run(Manifest::new(root.join("Cellar.toml"))?)?;
+        let records = load(&root.join("history.csv"))?;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].url(), "https://b.org");
+        assert_eq!(records[1].origin(), Some("pinboard".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn annotated_details() -> Result<(), Box<dyn std::error::Error>> {
+        let record = Record::new("https://a.org")
+            .with_title("A")
+            .with_summary("S")
+            .build()?;
+        let annotations = vec![
+            Annotation::new("https://a.org", "A passage")?.with_note("Why it matters"),
+            Annotation::new("https://b.org", "Elsewhere")?,
+        ];
+        let searched = searched(&record, &[], &annotations);
+
+        assert!(searched.contains(&"A passage"));
+        assert!(searched.contains(&"Why it matters"));
+        assert!(!searched.contains(&"Elsewhere"));
+
+        let value = with_details(record_json(&record), &[], &annotations, record.url());
+        assert_eq!(value["annotations"][0]["quote"], "A passage");
+        assert_eq!(value["annotations"].as_array().map(Vec::len), Some(1));
+
+        Ok(())
+    }
+}
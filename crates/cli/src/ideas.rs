@@ -5,14 +5,16 @@
 // according to those terms.
 
 use crate::error::Error;
+use crate::git;
+use crate::journal::Snapshot;
 use crate::manifest::Manifest;
-use chrono::prelude::*;
+use crate::store;
 use clap::Parser;
 use console::{Style, Term};
-use dialoguer::{theme::ColorfulTheme, Editor, Input, Select};
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::fs::OpenOptions;
+use curator_sketch::idea::{self, Idea};
+use dialoguer::{Input, Select};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 #[derive(Debug, Parser)]
@@ -27,13 +29,10 @@ pub enum Subcommand {
     Add(Add),
     /// Lists all ideas in the store.
     List(List),
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Idea {
-    date: String,
-    reminder: Option<String>,
-    content: String,
+    /// Exports every idea as a file in the given directory.
+    Export(Export),
+    /// Imports ideas from a directory previously exported.
+    Import(Import),
 }
 
 #[derive(Debug, Parser)]
@@ -41,18 +40,12 @@ pub struct Add;
 
 impl Add {
     pub fn run(&mut self, manifest: Manifest) -> Result<(), Error> {
-        let path = manifest.ideas_path();
-        let file = OpenOptions::new().append(true).create(true).open(path)?;
-        let metadata = file.metadata()?;
-        let empty_file = metadata.len() == 0;
-        let date = Utc::now().format("%F");
-        let mut wtr = csv::WriterBuilder::new()
-            .has_headers(empty_file)
-            .from_writer(file);
+        let path = manifest.ideas_path()?;
+        let settings = manifest.settings();
+        let theme = settings.theme();
 
-        let theme = ColorfulTheme::default();
-
-        let content = Editor::new()
+        let content = settings
+            .editor()
             .extension(".md")
             .trim_newlines(true)
             .edit("")
@@ -65,7 +58,7 @@ impl Add {
         let presets = vec!["tomorrow", "next week", "in an hour", "other"];
         let mut reminder: Option<String> = None;
 
-        if let Some(idx) = Select::with_theme(&theme)
+        if let Some(idx) = Select::with_theme(&*theme)
             .items(&presets)
             .with_prompt("Remind me")
             .interact_opt()?
@@ -75,7 +68,7 @@ impl Add {
                 1 => Some("P7D".to_string()),
                 2 => Some("PT1H".to_string()),
                 3 => {
-                    let val: String = Input::with_theme(&theme)
+                    let val: String = Input::with_theme(&*theme)
                         .with_prompt("Reminder (ISO8601)")
                         .allow_empty(true)
                         .interact()?;
@@ -90,16 +83,21 @@ impl Add {
             }
         }
 
-        let record = Idea {
-            date: date.to_string(),
-            content: content.unwrap(),
-            reminder,
-        };
-
-        wtr.serialize(record)?;
-        wtr.flush()?;
-
-        Ok(())
+        let record = Idea::new(content.unwrap())
+            .with_date(settings.today())
+            .with_tags(&settings.default_tags())
+            .with_reminder(reminder);
+        let message = format!("ideas: add {}", git::subject(record.summary()));
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut ideas = load(path)?;
+        // Ids have a one-second resolution, so two ideas added in the same
+        // second would otherwise share one.
+        idea::push(&mut ideas, record);
+
+        save(path, &ideas)?;
+
+        snapshot.commit(&manifest, &message)
     }
 }
 
@@ -123,11 +121,12 @@ impl List {
             "csv" => ListFormat::Csv,
             _ => unreachable!(),
         };
-        let path = manifest.ideas_path();
+        let path = manifest.ideas_path()?;
         let file = OpenOptions::new()
             .write(true)
             .read(true)
             .create(true)
+            .truncate(false)
             .open(path)?;
         let metadata = file.metadata()?;
 
@@ -143,10 +142,7 @@ impl List {
     }
 
     fn run_term(&self, file: File) -> Result<(), Error> {
-        let mut rdr = csv::Reader::from_reader(file);
-        // let date = Utc::today().format("%F");
-
-        // use std::time::Duration;
+        let ideas = idea::from_reader(file)?;
         let term = Term::stdout();
         let (_height, width) = term.size();
 
@@ -154,23 +150,23 @@ impl List {
         let head = Style::new().on_black().white();
 
         {
-            let headers = rdr.headers()?;
             let header = format!(
-                "###  {:10}  {:10}  {}",
-                headers.get(0).unwrap(),
-                headers.get(2).unwrap(),
-                headers.get(1).unwrap(),
+                "###  {:10}  {:10}  {:7}  {}",
+                "date", "reminder", "status", "content"
             );
             println!("{:80}", head.apply_to(header));
         }
 
-        for (idx, result) in rdr.deserialize().enumerate() {
-            let record: Idea = result?;
-            let date = &record.date;
-            let summary = &record.content.lines().next().unwrap();
-            let reminder = record.reminder.clone().unwrap_or("".to_string());
-            let row = format!("{:3}  {:10}  {:10}  {}", idx, &date, &reminder, &summary);
-            let padding = width as usize - row.len();
+        for (idx, record) in ideas.iter().enumerate() {
+            let row = format!(
+                "{:3}  {:10}  {:10}  {:7}  {}",
+                idx,
+                record.date(),
+                record.reminder().unwrap_or(""),
+                record.status(),
+                record.summary()
+            );
+            let padding = (width as usize).saturating_sub(row.len());
             let row_padded = format!("{}{}", row, " ".repeat(padding));
 
             if idx % 2 == 0 {
@@ -183,20 +179,108 @@ impl List {
     }
 
     fn run_csv(&self, file: File) -> Result<(), Error> {
-        let mut rdr = csv::Reader::from_reader(file);
-        let mut wtr = csv::Writer::from_writer(Term::stdout());
+        let mut ideas = idea::from_reader(file)?;
 
-        for result in rdr.deserialize() {
-            let mut record: Idea = result?;
+        if self.summary {
+            ideas = ideas
+                .into_iter()
+                .map(|record| record.clone().with_content(record.summary()))
+                .collect();
+        }
 
-            if self.summary {
-                record.content = record.content.lines().next().unwrap().to_string();
+        idea::to_writer(&ideas, Term::stdout())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Export {
+    /// The format of the exported files.
+    #[clap(long, short = 'f', default_value = "markdown", value_parser = ["markdown"])]
+    format: String,
+    /// The directory to write the ideas into.
+    dir: PathBuf,
+    /// Only exports the idea with this id.
+    #[clap(long, short = 'i')]
+    idea: Option<String>,
+}
+
+impl Export {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let store = manifest.ideas_path()?;
+        let mut ideas = load(store)?;
+        if let Some(id) = &self.idea {
+            ideas.retain(|idea| idea.id() == id);
+            if ideas.is_empty() {
+                return Err(Error::usage(format!("No idea with id {}", id)));
             }
+        }
+
+        fs::create_dir_all(&self.dir)?;
 
-            wtr.serialize(record)?;
+        for record in &ideas {
+            let name = record
+                .file_name()
+                .map_err(|err| Error::from(err).in_file(store))?;
+            let path = self.dir.join(name);
+            fs::write(path, record.to_markdown()?)?;
         }
-        wtr.flush()?;
+
+        println!("Exported {} ideas to {}", ideas.len(), self.dir.display());
 
         Ok(())
     }
 }
+
+#[derive(Debug, Parser)]
+pub struct Import {
+    /// The format of the files to import.
+    #[clap(long, short = 'f', default_value = "markdown", value_parser = ["markdown"])]
+    format: String,
+    /// The directory to read the ideas from.
+    dir: PathBuf,
+}
+
+impl Import {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.ideas_path()?;
+        let mut entries = fs::read_dir(&self.dir)
+            .map_err(|err| Error::from(err).in_file(&self.dir))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.retain(|p| p.extension().is_some_and(|ext| ext == "md"));
+        entries.sort();
+
+        let mut incoming = Vec::new();
+        for entry in entries {
+            let content = fs::read_to_string(&entry)?;
+            let record =
+                Idea::from_markdown(&content).map_err(|err| Error::from(err).in_file(&entry))?;
+            incoming.push(record);
+        }
+
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut ideas = load(path)?;
+        let (added, updated) = idea::merge(&mut ideas, incoming);
+
+        save(path, &ideas)?;
+
+        println!("Imported {} new ideas, updated {}", added, updated);
+
+        snapshot.commit(
+            &manifest,
+            &format!("ideas: import {} new, {} updated", added, updated),
+        )
+    }
+}
+
+/// Reads the idea store, treating a missing file as an empty store.
+pub fn load(path: &Path) -> Result<Vec<Idea>, Error> {
+    store::read_optional(path, idea::from_reader)
+}
+
+pub fn save(path: &Path, ideas: &[Idea]) -> Result<(), Error> {
+    store::replace(path, |file| Ok(idea::to_writer(ideas, file)?))
+}
@@ -0,0 +1,203 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::{Error, TERM_ERR};
+use crate::git;
+use crate::history::{self as history_cmd, prompt_record};
+use crate::journal::Snapshot;
+use crate::manifest::Manifest;
+use crate::store;
+use crate::tags;
+use clap::Parser;
+use curator_sketch::inbox::{self, Item, State};
+use curator_sketch::source;
+use dialoguer::Select;
+use std::path::Path;
+
+#[derive(Debug, Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum Subcommand {
+    /// Captures a link in the inbox store to triage later.
+    Add(Add),
+    /// Lists the items waiting for triage.
+    List(List),
+}
+
+#[derive(Debug, Parser)]
+pub struct Add {
+    /// The URL to capture.
+    url: String,
+    /// An optional title to remember what the link is about.
+    #[clap(long, short = 't')]
+    title: Option<String>,
+}
+
+impl Add {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.inbox_path()?;
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut items = load(path)?;
+
+        let mut item = Item::new(self.url.as_str());
+        if let Some(title) = &self.title {
+            item = item.with_title(title.as_str());
+        }
+
+        // Feed items have their own ids, so the URL is checked as well.
+        let open = items
+            .iter()
+            .any(|item| item.url() == self.url && item.is_open());
+        if open || !inbox::capture(&mut items, item) {
+            println!("Already in the inbox");
+            return Ok(());
+        }
+
+        save(path, &items)?;
+
+        snapshot.commit(&manifest, &format!("inbox: add {}", self.url))
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct List {
+    /// Lists every item, including the ones already triaged.
+    #[clap(long, short = 'a')]
+    all: bool,
+}
+
+impl List {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let items = load(manifest.inbox_path()?)?;
+
+        for item in items.iter().filter(|item| self.all || item.is_open()) {
+            println!(
+                "{:10}  {:9}  {}  {}",
+                item.date(),
+                item.state(),
+                item.url(),
+                item.title().unwrap_or("")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Triage;
+
+impl Triage {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let path = manifest.inbox_path()?;
+        let sources = store::read(manifest.sources_path()?, source::from_reader)?;
+        let records = history_cmd::load(manifest.history_path()?)?;
+        let model = tags::model(&manifest, &records)?;
+        // Decisions are saved one at a time under the lock, so other writers
+        // can add to the inbox while the triage waits for input.
+        let items = load(path)?;
+        let queue = triage_order(&items);
+
+        if queue.is_empty() {
+            println!("Nothing to triage");
+            return Ok(());
+        }
+
+        let settings = manifest.settings();
+        let theme = settings.theme();
+        let actions = ["keep", "defer", "discard", "quit"];
+        let mut decided = Vec::new();
+
+        for (position, idx) in queue.iter().enumerate() {
+            let item = &items[*idx];
+            let prompt = format!(
+                "[{}/{}] {} <{}>",
+                position + 1,
+                queue.len(),
+                item.title().unwrap_or("(untitled)"),
+                item.url()
+            );
+            let choice = Select::with_theme(&*theme)
+                .with_prompt(prompt)
+                .items(&actions)
+                .default(0)
+                .interact_opt()?;
+
+            let (state, record) = match choice {
+                Some(0) => {
+                    let draft = settings.draft(item.to_builder());
+                    match prompt_record(draft, &sources, &records, &model, settings) {
+                        Ok(record) => (State::Kept, Some(record)),
+                        Err(err) => {
+                            TERM_ERR.write_line(&format!("Not kept: {}", err))?;
+                            continue;
+                        }
+                    }
+                }
+                Some(1) => (State::Deferred, None),
+                Some(2) => (State::Discarded, None),
+                _ => break,
+            };
+
+            let _lock = manifest.lock()?;
+            let snapshot = Snapshot::take(&[path, manifest.history_path()?])?;
+            if let Some(record) = &record {
+                record.append_into(manifest.history_path()?)?;
+            }
+
+            let mut current = load(path)?;
+            if let Some(x) = current.iter_mut().find(|x| x.same_entry(item)) {
+                x.set_state(state);
+            }
+            save(path, &current)?;
+            snapshot.record(
+                &manifest,
+                &format!("inbox: triage {} {}", state, item.url()),
+            )?;
+            decided.push(state);
+        }
+
+        let _lock = manifest.lock()?;
+        let count = |state| decided.iter().filter(|&&x| x == state).count();
+        git::commit(
+            &manifest,
+            &[path, manifest.history_path()?],
+            &format!(
+                "inbox: triage {} kept, {} deferred, {} discarded",
+                count(State::Kept),
+                count(State::Deferred),
+                count(State::Discarded)
+            ),
+        )
+    }
+}
+
+/// Indexes of the items to triage, pending ones first.
+fn triage_order(items: &[Item]) -> Vec<usize> {
+    let pending = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.state() == State::Pending);
+    let deferred = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.state() == State::Deferred);
+
+    pending.chain(deferred).map(|(idx, _)| idx).collect()
+}
+
+fn load(path: &Path) -> Result<Vec<Item>, Error> {
+    store::read_optional(path, inbox::from_reader)
+}
+
+fn save(path: &Path, items: &[Item]) -> Result<(), Error> {
+    store::replace(path, |file| Ok(inbox::to_writer(items, file)?))
+}
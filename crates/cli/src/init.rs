@@ -0,0 +1,158 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::config::MANIFEST_NAME;
+use crate::error::Error;
+use crate::git;
+use crate::journal::JOURNAL_NAME;
+use crate::manifest::{self, Manifest, Store, VERSION};
+use crate::store::{self, LOCK_NAME};
+use crate::tags::MODEL_NAME;
+use clap::Parser;
+use curator_sketch::{annotation, history, idea, inbox, source, text};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TEMPLATE: &str = r#"# Curator cellar manifest.
+#
+# Store paths are relative to this file. Remove a store to disable it.
+
+version = {version}
+
+[activity]
+# Resources read and summarised.
+history = "history.csv"
+# Things that happened. No command writes to it yet.
+events = "events.csv"
+# Where resources come from: blogs, feeds, people.
+sources = "sources.csv"
+# Loose thoughts to come back to.
+ideas = "ideas.csv"
+# Links captured to triage later.
+inbox = "inbox.csv"
+# The readable text of the history pages.
+texts = "texts.csv"
+# Passages kept from the history pages.
+annotations = "annotations.csv"
+
+# Uncomment to save pages offline with `curator history archive`.
+# [archive]
+# dir = "archive"
+# format = "html"
+"#;
+
+const GIT_ON: &str = r#"
+# Commits every change to the stores.
+[git]
+
+# Overrides the user configuration for this cellar.
+# [settings]
+# editor = "vim"
+# theme = "colorful"
+# default_tags = []
+# timezone = "Europe/London"
+"#;
+
+const GIT_OFF: &str = r#"
+# Uncomment to commit every change to the stores.
+# [git]
+
+# Overrides the user configuration for this cellar.
+# [settings]
+# editor = "vim"
+# theme = "colorful"
+# default_tags = []
+# timezone = "Europe/London"
+"#;
+
+#[derive(Debug, Parser)]
+pub struct Init {
+    /// The directory to hold the cellar.
+    #[clap(default_value = ".")]
+    dir: PathBuf,
+    /// Turns the cellar into a git repository and commits every change.
+    #[clap(long)]
+    git: bool,
+}
+
+impl Init {
+    /// Creates whatever is missing from the cellar, leaving existing files
+    /// untouched.
+    pub fn run(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+
+        let path = self.dir.canonicalize()?.join(MANIFEST_NAME);
+        let mut created: Vec<PathBuf> = Vec::new();
+
+        if path.exists() {
+            println!("Using the existing {}", path.display());
+
+            if let Some(version) = manifest::upgrade(&path)? {
+                println!(
+                    "Upgraded {} from version {} to {}",
+                    path.display(),
+                    version,
+                    VERSION
+                );
+            }
+        } else {
+            let git = if self.git { GIT_ON } else { GIT_OFF };
+            let template = TEMPLATE.replace("{version}", &VERSION.to_string());
+            fs::write(&path, format!("{}{}", template, git))?;
+            created.push(path.clone());
+        }
+
+        let manifest = Manifest::new(&path)?;
+        // No command writes events yet, so there are no columns to create
+        // the events store with.
+        let stores: Vec<(&Path, &[&str])> = Store::ALL
+            .iter()
+            .filter_map(|store| {
+                let headers: &[&str] = match store {
+                    Store::History => history::HEADERS,
+                    Store::Events => return None,
+                    Store::Sources => source::HEADERS,
+                    Store::Ideas => idea::HEADERS,
+                    Store::Inbox => inbox::HEADERS,
+                    Store::Texts => text::HEADERS,
+                    Store::Annotations => annotation::HEADERS,
+                };
+                manifest.store_path(*store).ok().map(|path| (path, headers))
+            })
+            .collect();
+
+        for (path, headers) in stores {
+            if store::create(path, headers)? {
+                created.push(path.to_path_buf());
+            }
+        }
+
+        for path in &created {
+            println!("Created {}", path.display());
+        }
+
+        if self.git && git::init(manifest.root())? {
+            println!(
+                "Initialised a git repository in {}",
+                manifest.root().display()
+            );
+
+            let ignore = manifest.root().join(".gitignore");
+            if !ignore.exists() {
+                let ignored = [LOCK_NAME, JOURNAL_NAME, MODEL_NAME];
+                fs::write(&ignore, format!("{}\n", ignored.join("\n")))?;
+                created.push(ignore);
+            }
+        }
+
+        if !created.is_empty() {
+            let paths: Vec<&Path> = created.iter().map(|p| p.as_path()).collect();
+            git::commit(&manifest, &paths, "cellar: init")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! The operation journal behind `curator undo` and `curator redo`.
+//!
+//! Every change to the stores is journaled as the block of rows it replaced
+//! in each store, so it can be reversed as long as those rows are still
+//! where the change left them.
+
+use crate::error::Error;
+use crate::git;
+use crate::manifest::Manifest;
+use crate::store;
+use chrono::prelude::*;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// The journal file at the root of a cellar.
+pub const JOURNAL_NAME: &str = ".curator.journal";
+
+/// The number of operations kept in the journal.
+const LIMIT: usize = 100;
+
+type Row = Vec<String>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    date: String,
+    operation: String,
+    changes: Vec<Change>,
+    #[serde(default)]
+    undone: bool,
+}
+
+/// Rows `before` at `start` were replaced by rows `after`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Change {
+    /// The store path, relative to the cellar root.
+    store: PathBuf,
+    start: usize,
+    before: Vec<Row>,
+    after: Vec<Row>,
+}
+
+/// The rows of some stores before an operation.
+///
+/// Take it while holding the cellar lock, before reading the stores to
+/// change.
+#[derive(Debug)]
+pub struct Snapshot {
+    stores: Vec<(PathBuf, Vec<Row>)>,
+}
+
+impl Snapshot {
+    pub fn take(paths: &[&Path]) -> Result<Snapshot, Error> {
+        let stores = paths
+            .iter()
+            .map(|path| Ok((path.to_path_buf(), read_rows(path)?)))
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Snapshot { stores })
+    }
+
+    /// Journals whatever changed since the snapshot was taken.
+    pub fn record(self, manifest: &Manifest, operation: &str) -> Result<(), Error> {
+        let mut changes = Vec::new();
+
+        for (path, before) in self.stores {
+            let after = read_rows(&path)?;
+            let store = path
+                .strip_prefix(manifest.root())
+                .unwrap_or(&path)
+                .to_path_buf();
+
+            if let Some(change) = diff(store, before, after) {
+                changes.push(change);
+            }
+        }
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = load(manifest)?;
+        entries.retain(|entry| !entry.undone);
+        entries.push(Entry {
+            date: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            operation: operation.to_string(),
+            changes,
+            undone: false,
+        });
+
+        save(manifest, &entries)
+    }
+
+    /// Journals the operation and commits the snapshot stores to git.
+    pub fn commit(self, manifest: &Manifest, operation: &str) -> Result<(), Error> {
+        let paths: Vec<PathBuf> = self.stores.iter().map(|(path, _)| path.clone()).collect();
+        let paths: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+
+        self.record(manifest, operation)?;
+        git::commit(manifest, &paths, operation)
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Undo {
+    /// The number of operations to undo.
+    #[clap(default_value = "1")]
+    count: usize,
+}
+
+impl Undo {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let _lock = manifest.lock()?;
+        let mut entries = load(&manifest)?;
+
+        for _ in 0..self.count {
+            let idx = match entries.iter().rposition(|entry| !entry.undone) {
+                Some(idx) => idx,
+                None => {
+                    println!("Nothing to undo");
+                    break;
+                }
+            };
+
+            revert(&manifest, &entries[idx], true)?;
+            entries[idx].undone = true;
+            save(&manifest, &entries)?;
+            println!("Undone: {}", entries[idx].operation);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Redo {
+    /// The number of operations to redo.
+    #[clap(default_value = "1")]
+    count: usize,
+}
+
+impl Redo {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let _lock = manifest.lock()?;
+        let mut entries = load(&manifest)?;
+
+        for _ in 0..self.count {
+            let idx = match entries.iter().position(|entry| entry.undone) {
+                Some(idx) => idx,
+                None => {
+                    println!("Nothing to redo");
+                    break;
+                }
+            };
+
+            revert(&manifest, &entries[idx], false)?;
+            entries[idx].undone = false;
+            save(&manifest, &entries)?;
+            println!("Redone: {}", entries[idx].operation);
+        }
+
+        Ok(())
+    }
+}
+
+/// Reverses the entry when `undo`, replays it otherwise.
+///
+/// Every store is checked before any is written so a conflict leaves the
+/// cellar untouched.
+fn revert(manifest: &Manifest, entry: &Entry, undo: bool) -> Result<(), Error> {
+    let mut updates = Vec::new();
+
+    for change in &entry.changes {
+        let path = manifest.root().join(&change.store);
+        let (expected, replacement) = if undo {
+            (&change.after, &change.before)
+        } else {
+            (&change.before, &change.after)
+        };
+        let mut rows = read_rows(&path)?;
+        let end = change.start + expected.len();
+
+        if rows.get(change.start..end) != Some(&expected[..]) {
+            return Err(Error::usage(format!(
+                "Can't {} '{}': the rows it touched have changed since",
+                if undo { "undo" } else { "redo" },
+                entry.operation
+            ))
+            .in_file(&path));
+        }
+
+        rows.splice(change.start..end, replacement.iter().cloned());
+        updates.push((path, rows));
+    }
+
+    for (path, rows) in &updates {
+        store::replace(path, |file| write_rows(rows, file))?;
+    }
+
+    let paths: Vec<&Path> = updates.iter().map(|(path, _)| path.as_path()).collect();
+    let verb = if undo { "undo" } else { "redo" };
+
+    git::commit(
+        manifest,
+        &paths,
+        &format!("{}: {}", verb, git::subject(&entry.operation)),
+    )
+}
+
+/// The smallest block of rows that turns `before` into `after`.
+fn diff(store: PathBuf, before: Vec<Row>, after: Vec<Row>) -> Option<Change> {
+    if before == after {
+        return None;
+    }
+
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    Some(Change {
+        store,
+        start: prefix,
+        before: before[prefix..before.len() - suffix].to_vec(),
+        after: after[prefix..after.len() - suffix].to_vec(),
+    })
+}
+
+/// Reads every row of a store, headers included, as empty when missing.
+fn read_rows(path: &Path) -> Result<Vec<Row>, Error> {
+    store::read_optional(path, |file| {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file)
+            .records()
+            .map(|record| Ok(record?.iter().map(String::from).collect()))
+            .collect::<Result<Vec<Row>, csv::Error>>()
+    })
+}
+
+fn write_rows(rows: &[Row], file: File) -> Result<(), Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(file);
+
+    for row in rows {
+        wtr.write_record(row)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+fn load(manifest: &Manifest) -> Result<Vec<Entry>, Error> {
+    let path = manifest.root().join(JOURNAL_NAME);
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    BufReader::new(File::open(&path)?)
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            serde_json::from_str(&line?)
+                .map_err(|err| Error::data(format!("line {}: {}", idx + 1, err)).in_file(&path))
+        })
+        .collect()
+}
+
+fn save(manifest: &Manifest, entries: &[Entry]) -> Result<(), Error> {
+    let path = manifest.root().join(JOURNAL_NAME);
+    let skip = entries.len().saturating_sub(LIMIT);
+
+    store::replace(&path, |mut file| {
+        for entry in &entries[skip..] {
+            let line = serde_json::to_string(entry)
+                .map_err(|err| Error::from(format!("Couldn't journal: {}", err)))?;
+            writeln!(file, "{}", line)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(values: &[&str]) -> Vec<Row> {
+        values.iter().map(|v| vec![v.to_string()]).collect()
+    }
+
+    #[test]
+    fn diff_appended_rows() {
+        let change = diff(
+            PathBuf::from("history.csv"),
+            rows(&["url", "a"]),
+            rows(&["url", "a", "b", "c"]),
+        )
+        .unwrap();
+
+        assert_eq!(change.start, 2);
+        assert!(change.before.is_empty());
+        assert_eq!(change.after, rows(&["b", "c"]));
+    }
+
+    #[test]
+    fn diff_changed_row() {
+        let change = diff(
+            PathBuf::from("inbox.csv"),
+            rows(&["url", "a", "b", "c"]),
+            rows(&["url", "a", "B", "c"]),
+        )
+        .unwrap();
+
+        assert_eq!(change.start, 2);
+        assert_eq!(change.before, rows(&["b"]));
+        assert_eq!(change.after, rows(&["B"]));
+        assert!(diff(PathBuf::new(), rows(&["a"]), rows(&["a"])).is_none());
+    }
+}
@@ -4,34 +4,97 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use error::{Error, TERM_ERR, TERM_OUT};
+use std::process::exit;
+use std::time::Duration;
 
 //use curator_sketch;
+mod archive;
+mod browser;
+mod completions;
+mod config;
 mod error;
+mod fetch;
+mod git;
+mod graph;
 mod history;
 mod ideas;
+mod inbox;
+mod init;
+mod journal;
 mod manifest;
+mod native_host;
+mod readable;
+mod serve;
+mod sources;
+mod stats;
+mod store;
+mod tags;
+mod tui;
 
 #[derive(Debug, Parser)]
 enum Subcommand {
+    /// Lists the cellars registered in the user configuration.
+    Cellars(config::Cellars),
+    /// Prints the completion script for a shell.
+    Completions(completions::Completions),
+    /// Prints the values to complete the last word with.
+    #[clap(name = "__complete", hide = true)]
+    Complete(completions::Complete),
+    /// Exports the records, tags, sources and links as a graph.
+    Graph(graph::Graph),
     /// Manages the history store.
     #[clap(alias = "h")]
     History(history::Cmd),
     /// Manages the idea store.
     Ideas(ideas::Cmd),
+    /// Manages the inbox store.
+    Inbox(inbox::Cmd),
+    /// Creates a new cellar or completes an existing one.
+    Init(init::Init),
+    /// Talks to a browser extension through native messaging.
+    NativeHost(native_host::Cmd),
+    /// Serves the stores as a local HTTP JSON API.
+    Serve(serve::Serve),
+    /// Manages the source store.
+    Sources(sources::Cmd),
+    /// Summarises the curation habits recorded in the stores.
+    Stats(stats::Stats),
+    /// Works with the tags of the history.
+    Tags(tags::Cmd),
+    /// Walks through the inbox deciding what to keep.
+    Triage(inbox::Triage),
+    /// Shows the recent changes committed to the cellar.
+    Log(git::Log),
+    /// Browses the stores in a full-screen interface.
+    Tui(tui::Tui),
+    /// Reverses the last changes to the stores.
+    Undo(journal::Undo),
+    /// Replays the last undone changes.
+    Redo(journal::Redo),
 }
 
 #[derive(Debug, Parser)]
 #[clap(name = "curator", version)]
 struct Curator {
-    /// Path to the cellar Cellar.toml manifest
+    /// Path to the cellar Cellar.toml manifest. Defaults to $CURATOR_MANIFEST
+    /// or the nearest Cellar.toml from the current directory
+    #[clap(long, short = 'p', value_name = "path")]
+    manifest_path: Option<String>,
+
+    /// Name of a cellar registered in the user configuration
     #[clap(
         long,
-        short = 'p',
-        value_name = "path",
-        default_value = "./Cellar.toml"
+        short = 'c',
+        value_name = "name",
+        conflicts_with = "manifest_path"
     )]
-    manifest_path: String,
+    cellar: Option<String>,
+
+    /// Seconds to wait for other curator processes changing the cellar
+    #[clap(long, value_name = "seconds", default_value = "5")]
+    lock_timeout: u64,
 
     /// Verbose mode
     #[clap(short)]
@@ -41,29 +104,86 @@ struct Curator {
     subcommand: Subcommand,
 }
 
-fn main() -> Result<(), error::Error> {
+fn main() {
     let opts: Curator = Curator::parse();
-    let manifest = manifest::Manifest::new(&opts.manifest_path)?;
+
+    let code = match run(opts) {
+        Ok(()) => 0,
+        Err(error) => {
+            error.print_err().unwrap();
+            error.exit_code()
+        }
+    };
+
+    TERM_ERR.flush().unwrap();
+    TERM_OUT.flush().unwrap();
+
+    exit(code)
+}
+
+fn run(opts: Curator) -> Result<(), Error> {
+    match &opts.subcommand {
+        Subcommand::Init(cmd) => return cmd.run(),
+        Subcommand::Cellars(cmd) => return cmd.run(),
+        Subcommand::Completions(cmd) => return cmd.run(Curator::command()),
+        Subcommand::Complete(cmd) => return cmd.run(Curator::command()),
+        _ => {}
+    }
+
+    let manifest =
+        manifest::Manifest::discover(opts.manifest_path.as_deref(), opts.cellar.as_deref())?
+            .with_lock_timeout(Duration::from_secs(opts.lock_timeout));
 
     match opts.subcommand {
         Subcommand::History(o) => match o.subcommand {
             history::Subcommand::Add(mut cmd) => cmd.run(manifest),
+            history::Subcommand::Import(cmd) => cmd.run(manifest),
+            history::Subcommand::Copy(cmd) => cmd.run(manifest),
+            history::Subcommand::Archive(cmd) => cmd.run(manifest),
+            history::Subcommand::Open(cmd) => cmd.run(manifest),
+            history::Subcommand::Extract(cmd) => cmd.run(manifest),
+            history::Subcommand::List(cmd) => cmd.run(manifest),
+            history::Subcommand::Show(cmd) => cmd.run(manifest),
+            history::Subcommand::Related(cmd) => cmd.run(manifest),
+            history::Subcommand::Link(cmd) => cmd.run(manifest),
+            history::Subcommand::Unlink(cmd) => cmd.run(manifest),
+            history::Subcommand::Annotate(cmd) => cmd.run(manifest),
+            history::Subcommand::Status(cmd) => cmd.run(manifest),
+            history::Subcommand::Rate(cmd) => cmd.run(manifest),
         },
         Subcommand::Ideas(o) => match o.subcommand {
             ideas::Subcommand::Add(mut cmd) => cmd.run(manifest),
             ideas::Subcommand::List(cmd) => cmd.run(manifest),
+            ideas::Subcommand::Export(cmd) => cmd.run(manifest),
+            ideas::Subcommand::Import(cmd) => cmd.run(manifest),
+        },
+        Subcommand::Inbox(o) => match o.subcommand {
+            inbox::Subcommand::Add(cmd) => cmd.run(manifest),
+            inbox::Subcommand::List(cmd) => cmd.run(manifest),
+        },
+        Subcommand::Init(_)
+        | Subcommand::Cellars(_)
+        | Subcommand::Completions(_)
+        | Subcommand::Complete(_) => unreachable!(),
+        Subcommand::Sources(o) => match o.subcommand {
+            sources::Subcommand::Import(cmd) => cmd.run(manifest),
+            sources::Subcommand::Export(cmd) => cmd.run(manifest),
+            sources::Subcommand::Poll(cmd) => cmd.run(manifest),
+        },
+        Subcommand::NativeHost(o) => match o.subcommand {
+            native_host::Subcommand::Run(cmd) => cmd.run(manifest),
+            native_host::Subcommand::Manifest(cmd) => cmd.run(manifest),
         },
+        Subcommand::Graph(cmd) => cmd.run(manifest),
+        Subcommand::Serve(cmd) => cmd.run(manifest),
+        Subcommand::Stats(cmd) => cmd.run(manifest),
+        Subcommand::Tags(o) => match o.subcommand {
+            tags::Subcommand::Suggest(cmd) => cmd.run(manifest),
+        },
+        Subcommand::Triage(cmd) => cmd.run(manifest),
+        Subcommand::Log(cmd) => cmd.run(manifest),
+        Subcommand::Tui(cmd) => cmd.run(manifest),
+        Subcommand::Undo(cmd) => cmd.run(manifest),
+        Subcommand::Redo(cmd) => cmd.run(manifest),
     }
-
-    // let code = if let Some(error) = err {
-    //     error.print_err().unwrap();
-    //     1
-    // } else {
-    //     0
-    // };
-
-    // TERM_ERR.flush().unwrap();
-    // TERM_OUT.flush().unwrap();
-
-    // exit(code)
 }
@@ -4,57 +4,450 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
-use crate::error::Error;
+use crate::config::{self, Settings, UserConfig};
+use crate::error::{Error, ErrorKind};
+use crate::store::{self, Lock};
 use serde::Deserialize;
-use std::fs::File;
-use std::io::Read;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use toml;
+use std::time::Duration;
+
+/// The manifest format understood by this version of curator.
+pub const VERSION: u32 = 1;
+
+/// Upgrades the manifest content one version at a time: `MIGRATIONS[n]`
+/// turns a version `n` manifest into a version `n + 1` one.
+const MIGRATIONS: &[fn(&str) -> String] = &[v0_to_v1];
+
+/// Manifests before versioning only lack the `version` key.
+fn v0_to_v1(content: &str) -> String {
+    format!("version = 1\n\n{}", content)
+}
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Manifest {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    root: PathBuf,
+    #[serde(skip)]
+    lock_timeout: Duration,
+    /// Checked and migrated by `migrate` before deserialising.
+    #[allow(dead_code)]
+    version: u32,
+    #[serde(default)]
     activity: Activity,
+    git: Option<Git>,
+    archive: Option<Archive>,
+    #[serde(default)]
+    settings: Settings,
 }
 
 impl Manifest {
-    pub fn new(path: &str) -> Result<Manifest, Error> {
-        let mut content = String::new();
-        File::open(path).and_then(|mut f| f.read_to_string(&mut content))?;
+    /// Finds the manifest to use and layers its settings on top of the user
+    /// configuration.
+    pub fn discover(explicit: Option<&str>, cellar: Option<&str>) -> Result<Manifest, Error> {
+        let user = UserConfig::load()?;
+        let path = config::locate_manifest(explicit, cellar, &user)?;
+        let mut manifest = Manifest::new(path)?;
+        manifest.settings = user.settings().clone().layered(manifest.settings);
+
+        Ok(manifest)
+    }
 
-        let mut manifest: Manifest =
-            toml::from_str(&content).map_err(|err| Error::new(&err.to_string()))?;
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Manifest, Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => {
+                Error::config(format!("No manifest found at {}", path.display()))
+                    .with_hint("Run 'curator init' to create a cellar")
+            }
+            _ => Error::from(err).in_file(path),
+        })?;
+        let (content, _) = migrate(path, content)?;
 
-        if let Some(base) = Path::new(path).canonicalize()?.parent() {
-            manifest.activity.history = base.join(manifest.activity.history);
-            manifest.activity.events = base.join(manifest.activity.events);
-            manifest.activity.sources = base.join(manifest.activity.sources);
-            manifest.activity.ideas = base.join(manifest.activity.ideas);
+        let mut manifest: Manifest = toml::from_str(&content)
+            .map_err(|err| Error::from(err).with_kind(ErrorKind::Config).in_file(path))?;
+        manifest
+            .settings
+            .validate()
+            .map_err(|err| err.in_file(path))?;
+        manifest.path = path.to_path_buf();
+
+        if let Some(base) = path.canonicalize()?.parent() {
+            manifest.root = base.to_path_buf();
+        }
+
+        manifest.validate()?;
+
+        for store in Store::ALL {
+            if let Some(store_path) = manifest.activity.get_mut(*store) {
+                *store_path = manifest.root.join(&store_path);
+            }
+        }
+
+        if let Some(archive) = manifest.archive.as_mut() {
+            archive.dir = manifest.root.join(&archive.dir);
         }
 
         Ok(manifest)
     }
 
-    pub fn history_path(&self) -> &Path {
-        &self.activity.history
+    /// Checks every configured store, naming the offending key on failure.
+    fn validate(&self) -> Result<(), Error> {
+        let mut seen: Vec<(Store, PathBuf)> = Vec::new();
+
+        for store in Store::ALL {
+            let store_path = match self.activity.get(*store) {
+                Some(p) => p,
+                None => continue,
+            };
+            let invalid = |reason: String| {
+                Error::config(format!("Invalid 'activity.{}': {}", store, reason))
+                    .in_file(&self.path)
+            };
+
+            if store_path.as_os_str().is_empty() {
+                return Err(invalid("the path is empty".to_string()));
+            }
+
+            let full = self.root.join(store_path);
+            if full.is_dir() {
+                return Err(invalid(format!("{} is a directory", full.display())));
+            }
+
+            if let Some((other, _)) = seen.iter().find(|(_, p)| *p == full) {
+                return Err(invalid(format!("same path as 'activity.{}'", other)));
+            }
+
+            seen.push((*store, full));
+        }
+
+        Ok(())
+    }
+
+    /// Sets how long to wait for other writers before giving up.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    pub fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
+    /// Locks the cellar for a change. Hold the lock from reading the stores
+    /// until the change is committed.
+    pub fn lock(&self) -> Result<Lock, Error> {
+        store::lock(&self.root, self.lock_timeout)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    pub fn events_path(&self) -> &Path {
-        &self.activity.events
+    /// The directory holding the manifest.
+    pub fn root(&self) -> &Path {
+        &self.root
     }
 
-    pub fn sources_path(&self) -> &Path {
-        &self.activity.sources
+    pub fn settings(&self) -> &Settings {
+        &self.settings
     }
 
-    pub fn ideas_path(&self) -> &Path {
-        &self.activity.ideas
+    /// Whether every change should be committed to git.
+    pub fn auto_commit(&self) -> bool {
+        self.git.as_ref().is_some_and(|git| git.commit)
+    }
+
+    /// The paths of every configured store.
+    pub fn store_paths(&self) -> Vec<&Path> {
+        Store::ALL
+            .iter()
+            .filter_map(|store| self.activity.get(*store))
+            .map(|p| p.as_path())
+            .collect()
+    }
+
+    /// The path to the given store or an error explaining it is disabled.
+    pub fn store_path(&self, store: Store) -> Result<&Path, Error> {
+        self.activity
+            .get(store)
+            .map(|p| p.as_path())
+            .ok_or_else(|| {
+                Error::config(format!("The {} store is disabled", store))
+                    .in_file(&self.path)
+                    .with_hint(format!("Add '{}' to [activity] to enable it", store))
+            })
+    }
+
+    /// Where pages are saved offline or an error explaining how to set it.
+    pub fn archive(&self) -> Result<&Archive, Error> {
+        self.archive.as_ref().ok_or_else(|| {
+            Error::config("No archive configured")
+                .in_file(&self.path)
+                .with_hint("Add an [archive] section with a 'dir' to keep the pages in")
+        })
+    }
+
+    pub fn history_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::History)
+    }
+
+    pub fn sources_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::Sources)
+    }
+
+    pub fn ideas_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::Ideas)
+    }
+
+    pub fn inbox_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::Inbox)
+    }
+
+    pub fn texts_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::Texts)
+    }
+
+    pub fn annotations_path(&self) -> Result<&Path, Error> {
+        self.store_path(Store::Annotations)
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Saves the manifest at `path` upgraded to the current version, keeping the
+/// original as a `.bak` sibling.
+///
+/// Returns the version upgraded from, if the manifest was older.
+pub fn upgrade(path: &Path) -> Result<Option<u32>, Error> {
+    let content = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+    let (upgraded, version) = migrate(path, content.clone())?;
+
+    if version == VERSION {
+        return Ok(None);
+    }
+
+    fs::write(path.with_extension("toml.bak"), &content)?;
+    store::replace(path, |mut file| Ok(file.write_all(upgraded.as_bytes())?))?;
+
+    Ok(Some(version))
+}
+
+/// Brings the manifest content up to the current version in memory, along
+/// with the version it was in.
+///
+/// Older manifests keep working as they are; only `curator init` saves the
+/// upgrade, so reading a cellar never rewrites it.
+fn migrate(path: &Path, content: String) -> Result<(String, u32), Error> {
+    let value: toml::Value = toml::from_str(&content)
+        .map_err(|err| Error::from(err).with_kind(ErrorKind::Config).in_file(path))?;
+    let version = match value.get("version") {
+        None => 0,
+        Some(toml::Value::Integer(n)) if *n >= 0 => *n as u32,
+        Some(_) => {
+            return Err(
+                Error::config("Invalid 'version': expected a positive integer").in_file(path),
+            )
+        }
+    };
+
+    if version > VERSION {
+        return Err(Error::config(format!(
+            "Invalid 'version': version {} is newer than the supported {}",
+            version, VERSION
+        ))
+        .in_file(path)
+        .with_hint("Upgrade curator"));
+    }
+
+    let upgraded = MIGRATIONS[version as usize..]
+        .iter()
+        .fold(content, |content, migrate| migrate(&content));
+
+    Ok((upgraded, version))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    History,
+    Events,
+    Sources,
+    Ideas,
+    Inbox,
+    Texts,
+    Annotations,
+}
+
+impl Store {
+    pub const ALL: &'static [Store] = &[
+        Store::History,
+        Store::Events,
+        Store::Sources,
+        Store::Ideas,
+        Store::Inbox,
+        Store::Texts,
+        Store::Annotations,
+    ];
+}
+
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Store::History => write!(f, "history"),
+            Store::Events => write!(f, "events"),
+            Store::Sources => write!(f, "sources"),
+            Store::Ideas => write!(f, "ideas"),
+            Store::Inbox => write!(f, "inbox"),
+            Store::Texts => write!(f, "texts"),
+            Store::Annotations => write!(f, "annotations"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Activity {
-    history: PathBuf,
-    events: PathBuf,
-    sources: PathBuf,
-    ideas: PathBuf,
+    history: Option<PathBuf>,
+    events: Option<PathBuf>,
+    sources: Option<PathBuf>,
+    ideas: Option<PathBuf>,
+    inbox: Option<PathBuf>,
+    texts: Option<PathBuf>,
+    annotations: Option<PathBuf>,
+}
+
+impl Activity {
+    fn get(&self, store: Store) -> Option<&PathBuf> {
+        match store {
+            Store::History => self.history.as_ref(),
+            Store::Events => self.events.as_ref(),
+            Store::Sources => self.sources.as_ref(),
+            Store::Ideas => self.ideas.as_ref(),
+            Store::Inbox => self.inbox.as_ref(),
+            Store::Texts => self.texts.as_ref(),
+            Store::Annotations => self.annotations.as_ref(),
+        }
+    }
+
+    fn get_mut(&mut self, store: Store) -> Option<&mut PathBuf> {
+        match store {
+            Store::History => self.history.as_mut(),
+            Store::Events => self.events.as_mut(),
+            Store::Sources => self.sources.as_mut(),
+            Store::Ideas => self.ideas.as_mut(),
+            Store::Inbox => self.inbox.as_mut(),
+            Store::Texts => self.texts.as_mut(),
+            Store::Annotations => self.annotations.as_mut(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Archive {
+    /// The directory holding the saved pages and their index.
+    dir: PathBuf,
+    #[serde(default)]
+    format: ArchiveFormat,
+}
+
+impl Archive {
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.dir.join("index.csv")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    /// The page with its assets next to it, ready to open in a browser.
+    #[default]
+    Html,
+    /// A single WARC file with the page and its assets.
+    Warc,
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Html => write!(f, "html"),
+            ArchiveFormat::Warc => write!(f, "warc"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Git {
+    /// Commits the stores after every change. On unless disabled explicitly.
+    #[serde(default = "enabled")]
+    commit: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A manifest with the given content, removed with the directory.
+    fn cellar(content: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Cellar.toml");
+        fs::write(&path, content).unwrap();
+
+        (dir, path)
+    }
+
+    #[test]
+    fn upgrade_unversioned() {
+        let original = "[activity]\nhistory = \"history.csv\"\n";
+        let (_dir, path) = cellar(original);
+        let manifest = Manifest::new(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(manifest.history_path().is_ok());
+        assert!(manifest.store_path(Store::Events).is_err());
+
+        assert_eq!(upgrade(&path).unwrap(), Some(0));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("version = 1\n"));
+        assert_eq!(
+            fs::read_to_string(path.with_extension("toml.bak")).unwrap(),
+            original
+        );
+        assert_eq!(upgrade(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn duplicate_store_path() {
+        let (_dir, path) =
+            cellar("version = 1\n[activity]\nhistory = \"a.csv\"\ninbox = \"a.csv\"\n");
+        let actual = Manifest::new(&path).unwrap_err();
+
+        assert!(actual.to_string().contains("'activity.inbox'"));
+    }
+
+    #[test]
+    fn newer_version() {
+        let (_dir, path) = cellar("version = 99\n");
+        let actual = Manifest::new(&path).unwrap_err();
+
+        assert!(actual.to_string().contains("'version'"));
+    }
 }
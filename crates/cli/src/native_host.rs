@@ -0,0 +1,281 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! A WebExtension native messaging host, so a browser extension can reach
+//! the cellar without `curator serve` running.
+//!
+//! Messages are JSON documents prefixed by their length as a native-endian
+//! `u32`, both ways. Every request has a `type`:
+//!
+//! - `{"type": "add", "record": {"url", "title", "summary", "tags", ...}}`
+//! - `{"type": "lookup", "url": "..."}`
+//! - `{"type": "tags"}`
+//! - `{"type": "sources"}`
+//!
+//! and is answered with `{"ok": true, "result": ...}` or `{"ok": false,
+//! "error", "kind"}`, echoing the request `id` when it has one.
+
+use crate::error::Error;
+use crate::history;
+use crate::manifest::Manifest;
+use crate::serve::{self, Reply};
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// The name browsers know the host by.
+const HOST_NAME: &str = "net.seachess.curator";
+
+/// The largest message accepted from the browser, in bytes.
+const MAX_MESSAGE: u32 = 1024 * 1024;
+
+#[derive(Debug, Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum Subcommand {
+    /// Answers the messages of a browser extension on the standard streams.
+    Run(Run),
+    /// Writes a launcher for the host and prints its browser manifest.
+    Manifest(HostManifest),
+}
+
+#[derive(Debug, Parser)]
+pub struct Run {
+    /// Arguments added by the browser, e.g. the calling extension.
+    #[clap(hide = true, allow_hyphen_values = true)]
+    _browser_args: Vec<String>,
+}
+
+impl Run {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let mut input = stdin.lock();
+        let mut output = stdout.lock();
+
+        while let Some(message) = read_message(&mut input)? {
+            let response = match message {
+                Ok(bytes) => answer(&manifest, &bytes),
+                Err(reply) => respond(None, Err(reply)),
+            };
+
+            write_message(&mut output, &response)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Add { record: Value },
+    Lookup { url: String },
+    Tags,
+    Sources,
+}
+
+fn answer(manifest: &Manifest, bytes: &[u8]) -> Value {
+    let id = serde_json::from_slice::<Value>(bytes)
+        .ok()
+        .and_then(|value| value.get("id").cloned());
+    let message: Message = match serde_json::from_slice(bytes) {
+        Ok(message) => message,
+        Err(err) => {
+            let reply = Reply::failure(400, "usage", format!("Invalid message: {}", err));
+            return respond(id, Err(reply));
+        }
+    };
+
+    let result = match message {
+        Message::Add { record } => serve::create_record(manifest, &record.to_string()),
+        Message::Lookup { url } => match serve::get_record(manifest, &url) {
+            Err(reply) if reply.status == 404 => Ok(Reply::new(200, Value::Null)),
+            result => result,
+        },
+        Message::Tags => tags(manifest),
+        Message::Sources => serve::list_sources(manifest, &Vec::new()),
+    };
+
+    respond(id, result)
+}
+
+/// Every tag in the history, the most used first.
+fn tags(manifest: &Manifest) -> Result<Reply, Reply> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for record in history::load(manifest.history_path()?)? {
+        for tag in record.tags() {
+            *counts.entry(tag).or_insert(0) += 1;
+        }
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let tags: Vec<Value> = counts
+        .into_iter()
+        .map(|(tag, count)| json!({ "tag": tag, "count": count }))
+        .collect();
+
+    Ok(Reply::new(200, json!(tags)))
+}
+
+fn respond(id: Option<Value>, result: Result<Reply, Reply>) -> Value {
+    let mut response = match result {
+        Ok(reply) => json!({ "ok": true, "result": reply.body }),
+        Err(reply) => {
+            let mut body = reply.body;
+            body["ok"] = json!(false);
+            body
+        }
+    };
+
+    if let Some(id) = id {
+        response["id"] = id;
+    }
+
+    response
+}
+
+/// Reads the next message, `None` once the browser closes the stream.
+///
+/// A message too large to handle is skipped and reported as a failure.
+fn read_message<R: Read>(reader: &mut R) -> Result<Option<Result<Vec<u8>, Reply>>, Error> {
+    let mut prefix = [0; 4];
+
+    match reader.read_exact(&mut prefix) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let len = u32::from_ne_bytes(prefix);
+    if len > MAX_MESSAGE {
+        io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+        let reply = Reply::failure(413, "usage", "The message is too large");
+        return Ok(Some(Err(reply)));
+    }
+
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(Some(Ok(bytes)))
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &Value) -> Result<(), Error> {
+    let bytes = message.to_string().into_bytes();
+
+    writer.write_all(&(bytes.len() as u32).to_ne_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct HostManifest {
+    /// The browser to register the host with.
+    #[clap(long, short = 'b', value_parser = ["chrome", "chromium", "firefox"])]
+    browser: String,
+    /// The extension allowed to talk to the host: an extension id for
+    /// Chrome and Chromium, an add-on id for Firefox.
+    #[clap(long, short = 'e', value_name = "id")]
+    extension: String,
+    /// Where to write the launcher script the browser runs.
+    #[clap(long, short = 'l', value_name = "path")]
+    launcher: PathBuf,
+}
+
+impl HostManifest {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let exe = env::current_exe()?;
+        let cellar = manifest.path().canonicalize()?;
+        let script = format!(
+            "#!/bin/sh\nexec {} --manifest-path {} native-host run \"$@\"\n",
+            quote(&exe.to_string_lossy()),
+            quote(&cellar.to_string_lossy())
+        );
+
+        fs::write(&self.launcher, script)
+            .map_err(|err| Error::from(err).in_file(&self.launcher))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.launcher, fs::Permissions::from_mode(0o755))?;
+        }
+
+        let launcher = self.launcher.canonicalize()?;
+        let mut host = json!({
+            "name": HOST_NAME,
+            "description": "Curator cellar",
+            "path": launcher,
+            "type": "stdio",
+        });
+        match &self.browser[..] {
+            "firefox" => host["allowed_extensions"] = json!([self.extension]),
+            _ => {
+                host["allowed_origins"] = json!([format!("chrome-extension://{}/", self.extension)])
+            }
+        }
+
+        let json =
+            serde_json::to_string_pretty(&host).map_err(|err| Error::from(err.to_string()))?;
+        println!("{}", json);
+        eprintln!(
+            "Save it as {}.json in the {} native messaging hosts directory",
+            HOST_NAME, self.browser
+        );
+
+        Ok(())
+    }
+}
+
+/// Quotes a value for a POSIX shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({ "type": "tags" })).unwrap();
+        write_message(&mut buffer, &json!({ "type": "sources" })).unwrap();
+
+        let mut reader = &buffer[..];
+        let first = read_message(&mut reader).unwrap().unwrap().unwrap();
+        let second = read_message(&mut reader).unwrap().unwrap().unwrap();
+
+        assert_eq!(first, br#"{"type":"tags"}"#);
+        assert!(matches!(
+            serde_json::from_slice(&second).unwrap(),
+            Message::Sources
+        ));
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn failure_response() {
+        let reply = Reply::failure(404, "not_found", "nope");
+        let response = respond(Some(json!(7)), Err(reply));
+
+        assert_eq!(response["ok"], false);
+        assert_eq!(response["kind"], "not_found");
+        assert_eq!(response["id"], 7);
+    }
+}
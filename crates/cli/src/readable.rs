@@ -0,0 +1,224 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Extracts the readable text of a page, leaving out navigation, scripts
+//! and other boilerplate.
+//!
+//! The content is the first `article` or `main` element when the page has
+//! one. Otherwise it is the element holding the most paragraph text, the
+//! way readability tools guess it.
+
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+/// Elements never part of the readable text.
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "button", "select", "svg", "iframe", "canvas", "head",
+];
+
+/// Elements starting a new paragraph.
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "pre",
+    "blockquote",
+    "table",
+    "tr",
+    "figcaption",
+    "br",
+    "hr",
+];
+
+/// Paragraphs shorter than this count as boilerplate when scoring.
+const MIN_PARAGRAPH: usize = 25;
+
+/// The share of link text beyond which a paragraph is a list of links.
+const MAX_LINK_DENSITY: f32 = 0.5;
+
+/// The readable text of the page, as paragraphs separated by blank lines.
+pub fn extract(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let content = Selector::parse("article, main, [role=main]")
+        .ok()
+        .and_then(|selector| document.select(&selector).next())
+        .or_else(|| best_candidate(&document))
+        .unwrap_or_else(|| document.root_element());
+    let mut paragraphs = Vec::new();
+    let mut current = Paragraph::default();
+
+    render(content, false, &mut current, &mut paragraphs);
+    current.finish(&mut paragraphs);
+
+    paragraphs.join("\n\n")
+}
+
+/// The element whose paragraphs hold the most text, with half the score of
+/// a paragraph going to its grandparent too.
+fn best_candidate(document: &Html) -> Option<ElementRef<'_>> {
+    let selector = Selector::parse("p").ok()?;
+    let mut scores: HashMap<_, (ElementRef<'_>, usize)> = HashMap::new();
+
+    for paragraph in document.select(&selector) {
+        let len = paragraph.text().collect::<String>().trim().len();
+        if len < MIN_PARAGRAPH {
+            continue;
+        }
+
+        let parent = paragraph.parent().and_then(ElementRef::wrap);
+        let grandparent = parent.and_then(|p| p.parent()).and_then(ElementRef::wrap);
+
+        for (element, score) in [(parent, len), (grandparent, len / 2)] {
+            if let Some(element) = element {
+                scores.entry(element.id()).or_insert((element, 0)).1 += score;
+            }
+        }
+    }
+
+    scores
+        .into_values()
+        .max_by_key(|(element, score)| (*score, std::cmp::Reverse(element.id())))
+        .map(|(element, _)| element)
+}
+
+#[derive(Debug, Default)]
+struct Paragraph {
+    text: String,
+    /// The characters within links.
+    linked: usize,
+}
+
+impl Paragraph {
+    /// Appends the text with its whitespace collapsed, so inline elements
+    /// join the words around them as written.
+    fn push(&mut self, text: &str, linked: bool) {
+        if text.starts_with(char::is_whitespace) {
+            self.space();
+        }
+
+        for (idx, word) in text.split_whitespace().enumerate() {
+            if idx > 0 {
+                self.text.push(' ');
+            }
+            self.text.push_str(word);
+            if linked {
+                self.linked += word.len();
+            }
+        }
+
+        if text.ends_with(char::is_whitespace) {
+            self.space();
+        }
+    }
+
+    fn space(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with(' ') {
+            self.text.push(' ');
+        }
+    }
+
+    /// Adds the paragraph unless it is empty or mostly links.
+    fn finish(&mut self, paragraphs: &mut Vec<String>) {
+        let text = self.text.trim();
+        let density = self.linked as f32 / text.len().max(1) as f32;
+
+        if !text.is_empty() && density <= MAX_LINK_DENSITY {
+            paragraphs.push(text.to_string());
+        }
+
+        *self = Paragraph::default();
+    }
+}
+
+/// Adds the text within the element to the paragraphs, `linked` telling
+/// whether it is inside a link.
+fn render(
+    element: ElementRef<'_>,
+    linked: bool,
+    current: &mut Paragraph,
+    paragraphs: &mut Vec<String>,
+) {
+    let name = element.value().name();
+    let hidden = element.value().attr("hidden").is_some()
+        || element.value().attr("aria-hidden") == Some("true");
+
+    if SKIPPED.contains(&name) || hidden {
+        return;
+    }
+
+    let block = BLOCKS.contains(&name);
+    let linked = linked || name == "a";
+
+    if block {
+        current.finish(paragraphs);
+    }
+
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => current.push(text, linked),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    render(child, linked, current, paragraphs);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if block {
+        current.finish(paragraphs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_boilerplate() {
+        let html = r#"<html><head><title>T</title><style>p { }</style></head><body>
+            <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+            <div class="sidebar"><p>Short.</p></div>
+            <div class="post">
+              <h1>The <em>title</em></h1>
+              <p>The first paragraph is long enough to count as content.</p>
+              <script>alert(1)</script>
+              <p>The second one links <a href="/x">somewhere</a> once.</p>
+              <ul><li><a href="/a">A long list of links</a></li></ul>
+            </div>
+            <footer>Copyright</footer></body></html>"#;
+
+        assert_eq!(
+            extract(html),
+            "The title\n\n\
+             The first paragraph is long enough to count as content.\n\n\
+             The second one links somewhere once."
+        );
+    }
+
+    #[test]
+    fn prefers_article() {
+        let html = "<body><p>Outside the article but long enough to score.</p>\
+                    <article><p>Inside</p></article></body>";
+
+        assert_eq!(extract(html), "Inside");
+    }
+}
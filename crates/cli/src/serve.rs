@@ -0,0 +1,863 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! A local HTTP JSON API over the history, source and idea stores.
+//!
+//! Every request must carry an `Authorization: Bearer <token>` header with
+//! the `serve_token` of the user configuration. Records are addressed by
+//! their percent-encoded URL, sources and ideas by their identifier:
+//!
+//! - `GET /records?tag=&origin=&status=&min_rating=&q=`, `POST /records`
+//! - `GET|PUT|DELETE /records/{url}`
+//! - `GET /sources?type=&q=`, `POST /sources`
+//! - `GET|PUT|DELETE /sources/{id}`
+//! - `GET /ideas?status=&tag=&q=`, `POST /ideas`
+//! - `GET|PUT|DELETE /ideas/{id}`
+//!
+//! Failures answer with a `{"error", "kind"}` body, plus the offending
+//! `field` when a payload doesn't validate.
+
+use crate::config::UserConfig;
+use crate::error::{Error, ErrorKind};
+use crate::git;
+use crate::history;
+use crate::ideas;
+use crate::journal::Snapshot;
+use crate::manifest::Manifest;
+use crate::store;
+use chrono::prelude::*;
+use clap::Parser;
+use curator_sketch::history::{ReadingStatus, Record, RecordBuilder, RecordError};
+use curator_sketch::idea::{self, Idea, Status};
+use curator_sketch::source::{self, Source};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The largest request body accepted, in bytes.
+const MAX_BODY: u64 = 1024 * 1024;
+
+#[derive(Debug, Parser)]
+pub struct Serve {
+    /// The address to listen on.
+    #[clap(long, value_name = "address", default_value = "127.0.0.1:7117")]
+    bind: String,
+}
+
+impl Serve {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let token = UserConfig::load()?
+            .serve_token()
+            .map(String::from)
+            .ok_or_else(|| {
+                Error::config("No API token configured").with_hint(
+                    "Set 'serve_token' in the user configuration to the token clients must send",
+                )
+            })?;
+        let server = Server::http(&self.bind).map_err(|err| {
+            Error::unavailable(format!("Couldn't listen on {}: {}", self.bind, err))
+        })?;
+
+        println!("Listening on http://{}", self.bind);
+
+        for mut request in server.incoming_requests() {
+            let reply = if authorized(&request, &token) {
+                match read_body(&mut request) {
+                    Ok(body) => handle(&manifest, request.method(), request.url(), &body),
+                    Err(reply) => reply,
+                }
+            } else {
+                Reply::failure(401, "unauthorized", "Missing or wrong bearer token")
+            };
+
+            println!("{} {} {}", request.method(), request.url(), reply.status);
+
+            if let Err(err) = request.respond(reply.into_response()) {
+                eprintln!("Couldn't respond: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A JSON response, or failure when used as the error of a handler.
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    pub fn new(status: u16, body: Value) -> Self {
+        Reply { status, body }
+    }
+
+    pub fn failure<S: Into<String>>(status: u16, kind: &str, message: S) -> Self {
+        Reply::new(status, json!({ "error": message.into(), "kind": kind }))
+    }
+
+    fn not_found<S: Into<String>>(message: S) -> Self {
+        Reply::failure(404, "not_found", message)
+    }
+
+    fn conflict<S: Into<String>>(message: S) -> Self {
+        Reply::failure(409, "conflict", message)
+    }
+
+    /// A payload that doesn't validate, naming the offending field.
+    fn invalid<S: Into<String>>(field: &str, message: S) -> Self {
+        Reply::new(
+            422,
+            json!({ "error": message.into(), "kind": "invalid", "field": field }),
+        )
+    }
+
+    fn into_response(self) -> Response<std::io::Cursor<Vec<u8>>> {
+        let mut response = Response::from_string(self.body.to_string())
+            .with_status_code(self.status)
+            .with_header(header("Content-Type", "application/json"));
+
+        if self.status == 401 {
+            response.add_header(header("WWW-Authenticate", "Bearer"));
+        }
+
+        response
+    }
+}
+
+impl From<Error> for Reply {
+    fn from(err: Error) -> Reply {
+        let status = match err.kind() {
+            ErrorKind::Usage => 400,
+            ErrorKind::Unavailable => 502,
+            ErrorKind::Busy => 503,
+            _ => 500,
+        };
+        let mut body = json!({ "error": err.to_string(), "kind": err.kind().to_string() });
+
+        if let Some(hint) = err.hint() {
+            body["hint"] = json!(hint);
+        }
+
+        Reply::new(status, body)
+    }
+}
+
+impl From<RecordError> for Reply {
+    fn from(err: RecordError) -> Reply {
+        match err {
+            RecordError::MissingTitle => Reply::invalid("title", err.to_string()),
+            RecordError::MissingSummary => Reply::invalid("summary", err.to_string()),
+            RecordError::InvalidStatus(_) => Reply::invalid("status", err.to_string()),
+            RecordError::InvalidRating(_) => Reply::invalid("rating", err.to_string()),
+            err => Error::from(err).into(),
+        }
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("A valid header")
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .is_some_and(|h| same(h.value.as_str().as_bytes(), expected.as_bytes()))
+}
+
+/// Compares in constant time so the token can't be guessed by timing.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn read_body(request: &mut Request) -> Result<String, Reply> {
+    let mut body = String::new();
+
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_string(&mut body)
+        .map_err(|err| Reply::failure(400, "usage", format!("Unreadable body: {}", err)))?;
+
+    if body.len() as u64 > MAX_BODY {
+        return Err(Reply::failure(413, "usage", "The body is too large"));
+    }
+
+    Ok(body)
+}
+
+/// Routes a request to its handler.
+fn handle(manifest: &Manifest, method: &Method, target: &str, body: &str) -> Reply {
+    let (segments, query) = match parse_target(target) {
+        Ok(parts) => parts,
+        Err(reply) => return reply,
+    };
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let result = match (method, &segments[..]) {
+        (Method::Get, ["records"]) => list_records(manifest, &query),
+        (Method::Post, ["records"]) => create_record(manifest, body),
+        (Method::Get, ["records", url]) => get_record(manifest, url),
+        (Method::Put, ["records", url]) => update_record(manifest, url, body),
+        (Method::Delete, ["records", url]) => delete_record(manifest, url),
+        (Method::Get, ["sources"]) => list_sources(manifest, &query),
+        (Method::Post, ["sources"]) => create_source(manifest, body),
+        (Method::Get, ["sources", id]) => get_source(manifest, id),
+        (Method::Put, ["sources", id]) => update_source(manifest, id, body),
+        (Method::Delete, ["sources", id]) => delete_source(manifest, id),
+        (Method::Get, ["ideas"]) => list_ideas(manifest, &query),
+        (Method::Post, ["ideas"]) => create_idea(manifest, body),
+        (Method::Get, ["ideas", id]) => get_idea(manifest, id),
+        (Method::Put, ["ideas", id]) => update_idea(manifest, id, body),
+        (Method::Delete, ["ideas", id]) => delete_idea(manifest, id),
+        (_, ["records"]) | (_, ["sources"]) | (_, ["ideas"]) => Err(not_allowed(method)),
+        (_, ["records", _]) | (_, ["sources", _]) | (_, ["ideas", _]) => Err(not_allowed(method)),
+        _ => Err(Reply::not_found(format!("No endpoint at {}", target))),
+    };
+
+    result.unwrap_or_else(|reply| reply)
+}
+
+fn not_allowed(method: &Method) -> Reply {
+    Reply::failure(405, "usage", format!("{} is not allowed here", method))
+}
+
+pub type Query = Vec<(String, String)>;
+
+/// Splits a request target into its decoded path segments and query pairs.
+fn parse_target(target: &str) -> Result<(Vec<String>, Query), Reply> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| decode(s, false))
+        .collect::<Result<_, _>>()?;
+    let query = query
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((decode(key, true)?, decode(value, true)?))
+        })
+        .collect::<Result<_, Reply>>()?;
+
+    Ok((segments, query))
+}
+
+/// Decodes percent-encoded text, and `+` as a space in query strings.
+fn decode(text: &str, query: bool) -> Result<String, Reply> {
+    let invalid = || {
+        Reply::failure(
+            400,
+            "usage",
+            format!("Invalid percent-encoding in '{}'", text),
+        )
+    };
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' => {
+                let hex = text.get(idx + 1..idx + 3).ok_or_else(invalid)?;
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                idx += 3;
+            }
+            b'+' if query => {
+                decoded.push(b' ');
+                idx += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+fn param<'a>(query: &'a Query, key: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Whether any of the texts contains the `q` parameter, ignoring case.
+fn contains(query: &Query, texts: &[&str]) -> bool {
+    match param(query, "q") {
+        Some(q) => {
+            let q = q.to_lowercase();
+            texts.iter().any(|text| text.to_lowercase().contains(&q))
+        }
+        None => true,
+    }
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, Reply> {
+    serde_json::from_str(body)
+        .map_err(|err| Reply::failure(400, "usage", format!("Invalid JSON body: {}", err)))
+}
+
+/// Tells an explicit `null`, as `Some(None)`, from a missing field.
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Blank strings count as missing.
+fn present(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+fn source_json(src: &Source) -> Value {
+    json!({
+        "id": src.id(),
+        "type": src.type_(),
+        "url": src.url(),
+        "comment": src.comment(),
+    })
+}
+
+fn idea_json(idea: &Idea) -> Value {
+    json!({
+        "id": idea.id(),
+        "date": idea.date(),
+        "reminder": idea.reminder(),
+        "status": idea.status().to_string(),
+        "tags": idea.tags(),
+        "content": idea.content(),
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecordBody {
+    url: Option<String>,
+    date: Option<NaiveDate>,
+    title: Option<String>,
+    summary: Option<String>,
+    tags: Option<Vec<String>>,
+    origin: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    status: Option<Option<ReadingStatus>>,
+    #[serde(default, deserialize_with = "nullable")]
+    rating: Option<Option<u8>>,
+}
+
+impl RecordBody {
+    /// Sets every value given on top of the builder. A `null` status or
+    /// rating clears it.
+    fn apply(self, mut builder: RecordBuilder) -> RecordBuilder {
+        if let Some(url) = present(self.url) {
+            builder = builder.with_url(url);
+        }
+        if let Some(date) = self.date {
+            builder = builder.with_date(date);
+        }
+        if let Some(title) = present(self.title) {
+            builder = builder.with_title(title);
+        }
+        if let Some(summary) = present(self.summary) {
+            builder = builder.with_summary(summary);
+        }
+        if let Some(tags) = self.tags {
+            let tags: Vec<&str> = tags.iter().map(|tag| tag.as_str()).collect();
+            builder = builder.with_tags(&tags);
+        }
+        if let Some(origin) = present(self.origin) {
+            builder = builder.with_origin(origin);
+        }
+        match self.status {
+            Some(Some(status)) => builder = builder.with_status(status),
+            Some(None) => builder = builder.without_status(),
+            None => (),
+        }
+        match self.rating {
+            Some(Some(rating)) => builder = builder.with_rating(rating),
+            Some(None) => builder = builder.without_rating(),
+            None => (),
+        }
+
+        builder
+    }
+
+    /// A new record, dated today with the default tags unless given.
+    fn create(self, manifest: &Manifest) -> Result<Record, Reply> {
+        let url = present(self.url.clone())
+            .ok_or_else(|| Reply::invalid("url", "'url' is a required field"))?;
+        let date = self.date;
+        let mut builder = manifest.settings().draft(self.apply(Record::new(url)));
+
+        if let Some(date) = date {
+            builder = builder.with_date(date);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+fn list_records(manifest: &Manifest, query: &Query) -> Result<Reply, Reply> {
+    let records = history::load(manifest.history_path()?)?;
+    let texts = history::load_texts(manifest)?;
+    let annotations = history::load_annotations(manifest)?;
+    let tag = param(query, "tag");
+    let origin = param(query, "origin");
+    let status = match param(query, "status") {
+        Some(status) => Some(
+            status
+                .parse::<ReadingStatus>()
+                .map_err(|err| Reply::failure(400, "usage", err.to_string()))?,
+        ),
+        None => None,
+    };
+    let min_rating =
+        match param(query, "min_rating") {
+            Some(rating) => Some(rating.parse::<u8>().map_err(|_| {
+                Reply::failure(400, "usage", format!("Invalid rating '{}'", rating))
+            })?),
+            None => None,
+        };
+    let found: Vec<Value> = records
+        .iter()
+        .filter(|record| tag.is_none_or(|tag| record.tags().iter().any(|t| t == tag)))
+        .filter(|record| origin.is_none_or(|origin| record.origin().as_deref() == Some(origin)))
+        .filter(|record| status.is_none_or(|status| record.status() == Some(status)))
+        .filter(|record| min_rating.is_none_or(|min| record.rating().is_some_and(|r| r >= min)))
+        .filter(|record| contains(query, &history::searched(record, &texts, &annotations)))
+        .map(|record| {
+            history::with_details(
+                history::record_json(record),
+                &texts,
+                &annotations,
+                record.url(),
+            )
+        })
+        .collect();
+
+    Ok(Reply::new(200, json!(found)))
+}
+
+fn find_record(records: &[Record], url: &str) -> Result<usize, Reply> {
+    records
+        .iter()
+        .position(|record| record.url() == url)
+        .ok_or_else(|| Reply::not_found(format!("No record for {} in the history", url)))
+}
+
+pub fn get_record(manifest: &Manifest, url: &str) -> Result<Reply, Reply> {
+    let records = history::load(manifest.history_path()?)?;
+    let idx = find_record(&records, url)?;
+    let texts = history::load_texts(manifest)?;
+    let annotations = history::load_annotations(manifest)?;
+
+    Ok(Reply::new(
+        200,
+        history::with_details(
+            history::record_json(&records[idx]),
+            &texts,
+            &annotations,
+            url,
+        ),
+    ))
+}
+
+pub fn create_record(manifest: &Manifest, body: &str) -> Result<Reply, Reply> {
+    let record = parse_body::<RecordBody>(body)?.create(manifest)?;
+    let path = manifest.history_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+
+    if history::load(path)?.iter().any(|x| x.url() == record.url()) {
+        return Err(Reply::conflict(format!(
+            "{} is already in the history",
+            record.url()
+        )));
+    }
+
+    record.append_into(path)?;
+    snapshot.commit(
+        manifest,
+        &format!("history: add {}", git::subject(record.title())),
+    )?;
+
+    Ok(Reply::new(201, history::record_json(&record)))
+}
+
+fn update_record(manifest: &Manifest, url: &str, body: &str) -> Result<Reply, Reply> {
+    let body: RecordBody = parse_body(body)?;
+    let path = manifest.history_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut records = history::load(path)?;
+    let idx = find_record(&records, url)?;
+    let builder = body.apply(records[idx].to_builder());
+
+    if builder.url() != url && records.iter().any(|x| x.url() == builder.url()) {
+        return Err(Reply::conflict(format!(
+            "{} is already in the history",
+            builder.url()
+        )));
+    }
+
+    records[idx] = builder.build()?;
+    history::save(path, &records)?;
+    snapshot.commit(
+        manifest,
+        &format!("history: edit {}", git::subject(records[idx].title())),
+    )?;
+
+    Ok(Reply::new(200, history::record_json(&records[idx])))
+}
+
+fn delete_record(manifest: &Manifest, url: &str) -> Result<Reply, Reply> {
+    let path = manifest.history_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut records = history::load(path)?;
+    let record = records.remove(find_record(&records, url)?);
+
+    history::save(path, &records)?;
+    snapshot.commit(
+        manifest,
+        &format!("history: delete {}", git::subject(record.title())),
+    )?;
+
+    Ok(Reply::new(200, history::record_json(&record)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceBody {
+    id: Option<String>,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    url: Option<String>,
+    comment: Option<String>,
+}
+
+impl SourceBody {
+    /// Sets every value given on top of the source, if any.
+    fn apply(self, src: Option<&Source>) -> Result<Source, Reply> {
+        let id = present(self.id).or_else(|| src.map(|s| s.id().to_string()));
+        let type_ = present(self.type_).or_else(|| src.map(|s| s.type_().to_string()));
+        let url = present(self.url).or_else(|| src.map(|s| s.url().to_string()));
+        let comment = self
+            .comment
+            .or_else(|| src.map(|s| s.comment().to_string()))
+            .unwrap_or_default();
+        let id = id.ok_or_else(|| Reply::invalid("id", "'id' is a required field"))?;
+        source::check_id(&id).map_err(|err| Reply::invalid("id", err.to_string()))?;
+
+        Ok(Source::new(
+            &id,
+            &type_.ok_or_else(|| Reply::invalid("type", "'type' is a required field"))?,
+            &url.ok_or_else(|| Reply::invalid("url", "'url' is a required field"))?,
+            &comment,
+        ))
+    }
+}
+
+fn load_sources(path: &Path) -> Result<Vec<Source>, Reply> {
+    Ok(store::read(path, source::from_reader)?)
+}
+
+fn save_sources(path: &Path, sources: &[Source]) -> Result<(), Reply> {
+    Ok(store::replace(path, |file| {
+        Ok(source::to_writer(sources, file)?)
+    })?)
+}
+
+fn find_source(sources: &[Source], id: &str) -> Result<usize, Reply> {
+    sources
+        .iter()
+        .position(|src| src.id() == id)
+        .ok_or_else(|| Reply::not_found(format!("No source '{}'", id)))
+}
+
+pub fn list_sources(manifest: &Manifest, query: &Query) -> Result<Reply, Reply> {
+    let sources = load_sources(manifest.sources_path()?)?;
+    let type_ = param(query, "type");
+    let found: Vec<Value> = sources
+        .iter()
+        .filter(|src| type_.is_none_or(|type_| src.type_() == type_))
+        .filter(|src| contains(query, &[src.id(), src.url(), src.comment()]))
+        .map(source_json)
+        .collect();
+
+    Ok(Reply::new(200, json!(found)))
+}
+
+fn get_source(manifest: &Manifest, id: &str) -> Result<Reply, Reply> {
+    let sources = load_sources(manifest.sources_path()?)?;
+    let idx = find_source(&sources, id)?;
+
+    Ok(Reply::new(200, source_json(&sources[idx])))
+}
+
+fn create_source(manifest: &Manifest, body: &str) -> Result<Reply, Reply> {
+    let src = parse_body::<SourceBody>(body)?.apply(None)?;
+    let path = manifest.sources_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+
+    if load_sources(path)?.iter().any(|x| x.id() == src.id()) {
+        return Err(Reply::conflict(format!(
+            "The source '{}' already exists",
+            src.id()
+        )));
+    }
+
+    src.append_into(path).map_err(Error::from)?;
+    snapshot.commit(manifest, &format!("sources: add {}", src.id()))?;
+
+    Ok(Reply::new(201, source_json(&src)))
+}
+
+fn update_source(manifest: &Manifest, id: &str, body: &str) -> Result<Reply, Reply> {
+    let body: SourceBody = parse_body(body)?;
+    let path = manifest.sources_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut sources = load_sources(path)?;
+    let idx = find_source(&sources, id)?;
+    let src = body.apply(Some(&sources[idx]))?;
+
+    if src.id() != id && sources.iter().any(|x| x.id() == src.id()) {
+        return Err(Reply::conflict(format!(
+            "The source '{}' already exists",
+            src.id()
+        )));
+    }
+
+    sources[idx] = src;
+    save_sources(path, &sources)?;
+    snapshot.commit(manifest, &format!("sources: edit {}", sources[idx].id()))?;
+
+    Ok(Reply::new(200, source_json(&sources[idx])))
+}
+
+fn delete_source(manifest: &Manifest, id: &str) -> Result<Reply, Reply> {
+    let path = manifest.sources_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut sources = load_sources(path)?;
+    let src = sources.remove(find_source(&sources, id)?);
+
+    save_sources(path, &sources)?;
+    snapshot.commit(manifest, &format!("sources: delete {}", src.id()))?;
+
+    Ok(Reply::new(200, source_json(&src)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IdeaBody {
+    content: Option<String>,
+    reminder: Option<String>,
+    status: Option<Status>,
+    tags: Option<Vec<String>>,
+}
+
+impl IdeaBody {
+    /// Sets every value given on top of the idea. A blank reminder clears it.
+    fn apply(self, mut idea: Idea) -> Idea {
+        if let Some(content) = present(self.content) {
+            idea = idea.with_content(content);
+        }
+        if let Some(reminder) = self.reminder {
+            idea = idea.with_reminder(present(Some(reminder)));
+        }
+        if let Some(status) = self.status {
+            idea = idea.with_status(status);
+        }
+        if let Some(tags) = self.tags {
+            let tags: Vec<&str> = tags.iter().map(|tag| tag.as_str()).collect();
+            idea = idea.with_tags(&tags);
+        }
+
+        idea
+    }
+
+    /// A new idea, dated today with the default tags unless given.
+    fn create(self, manifest: &Manifest) -> Result<Idea, Reply> {
+        let settings = manifest.settings();
+        let content = present(self.content.clone())
+            .ok_or_else(|| Reply::invalid("content", "'content' is a required field"))?;
+        let idea = Idea::new(content)
+            .with_date(settings.today())
+            .with_tags(&settings.default_tags());
+
+        Ok(self.apply(idea))
+    }
+}
+
+fn find_idea(ideas: &[Idea], id: &str) -> Result<usize, Reply> {
+    ideas
+        .iter()
+        .position(|idea| idea.id() == id)
+        .ok_or_else(|| Reply::not_found(format!("No idea '{}'", id)))
+}
+
+fn list_ideas(manifest: &Manifest, query: &Query) -> Result<Reply, Reply> {
+    let ideas = ideas::load(manifest.ideas_path()?)?;
+    let status =
+        match param(query, "status") {
+            Some(status) => Some(serde_json::from_value::<Status>(json!(status)).map_err(
+                |_| Reply::failure(400, "usage", format!("Unknown status '{}'", status)),
+            )?),
+            None => None,
+        };
+    let tag = param(query, "tag");
+    let found: Vec<Value> = ideas
+        .iter()
+        .filter(|idea| status.is_none_or(|status| idea.status() == status))
+        .filter(|idea| tag.is_none_or(|tag| idea.tags().iter().any(|t| t == tag)))
+        .filter(|idea| contains(query, &[idea.content()]))
+        .map(idea_json)
+        .collect();
+
+    Ok(Reply::new(200, json!(found)))
+}
+
+fn get_idea(manifest: &Manifest, id: &str) -> Result<Reply, Reply> {
+    let ideas = ideas::load(manifest.ideas_path()?)?;
+    let idx = find_idea(&ideas, id)?;
+
+    Ok(Reply::new(200, idea_json(&ideas[idx])))
+}
+
+fn create_idea(manifest: &Manifest, body: &str) -> Result<Reply, Reply> {
+    let idea = parse_body::<IdeaBody>(body)?.create(manifest)?;
+    let path = manifest.ideas_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut ideas = ideas::load(path)?;
+    let reply = Reply::new(201, idea_json(idea::push(&mut ideas, idea)));
+
+    ideas::save(path, &ideas)?;
+    snapshot.commit(
+        manifest,
+        &format!(
+            "ideas: add {}",
+            git::subject(ideas[ideas.len() - 1].summary())
+        ),
+    )?;
+
+    Ok(reply)
+}
+
+fn update_idea(manifest: &Manifest, id: &str, body: &str) -> Result<Reply, Reply> {
+    let body: IdeaBody = parse_body(body)?;
+    let path = manifest.ideas_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut ideas = ideas::load(path)?;
+    let idx = find_idea(&ideas, id)?;
+
+    ideas[idx] = body.apply(ideas[idx].clone());
+    ideas::save(path, &ideas)?;
+    snapshot.commit(
+        manifest,
+        &format!("ideas: edit {}", git::subject(ideas[idx].summary())),
+    )?;
+
+    Ok(Reply::new(200, idea_json(&ideas[idx])))
+}
+
+fn delete_idea(manifest: &Manifest, id: &str) -> Result<Reply, Reply> {
+    let path = manifest.ideas_path()?;
+    let _lock = manifest.lock()?;
+    let snapshot = Snapshot::take(&[path])?;
+    let mut ideas = ideas::load(path)?;
+    let idea = ideas.remove(find_idea(&ideas, id)?);
+
+    ideas::save(path, &ideas)?;
+    snapshot.commit(
+        manifest,
+        &format!("ideas: delete {}", git::subject(idea.summary())),
+    )?;
+
+    Ok(Reply::new(200, idea_json(&idea)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_parts() {
+        let (segments, query) =
+            parse_target("/records/https%3A%2F%2Fa.org%2F1?q=rust+cli&tag=").unwrap();
+
+        assert_eq!(segments, vec!["records", "https://a.org/1"]);
+        assert_eq!(param(&query, "q"), Some("rust cli"));
+        assert_eq!(param(&query, "tag"), Some(""));
+        assert_eq!(parse_target("/ideas/%zz").unwrap_err().status, 400);
+    }
+
+    #[test]
+    fn record_validation() {
+        let body: RecordBody =
+            serde_json::from_str(r#"{"url": "https://a.org", "title": "A", "summary": " "}"#)
+                .unwrap();
+        let reply = Reply::from(body.apply(Record::new("")).build().unwrap_err());
+
+        assert_eq!(reply.status, 422);
+        assert_eq!(reply.body["field"], "summary");
+        assert_eq!(Reply::from(Error::busy("locked")).status, 503);
+    }
+
+    #[test]
+    fn record_clear_status() {
+        let record = Record::new("https://a.org")
+            .with_title("A")
+            .with_summary("S")
+            .with_status(ReadingStatus::Read)
+            .with_rating(4)
+            .build()
+            .unwrap();
+        let kept: RecordBody = serde_json::from_str(r#"{"title": "B"}"#).unwrap();
+        let kept = kept.apply(record.to_builder()).build().unwrap();
+        let cleared: RecordBody =
+            serde_json::from_str(r#"{"status": null, "rating": null}"#).unwrap();
+        let cleared = cleared.apply(record.to_builder()).build().unwrap();
+
+        assert_eq!(kept.status(), Some(ReadingStatus::Read));
+        assert_eq!(kept.rating(), Some(4));
+        assert_eq!(cleared.status(), None);
+        assert_eq!(cleared.rating(), None);
+    }
+
+    #[test]
+    fn source_ids() {
+        let body: SourceBody =
+            serde_json::from_str(r#"{"id": "a/b", "type": "feed", "url": "https://a.org"}"#)
+                .unwrap();
+        let reply = body.apply(None).unwrap_err();
+
+        assert_eq!(reply.status, 422);
+        assert_eq!(reply.body["field"], "id");
+
+        let body: SourceBody = serde_json::from_str(
+            r#"{"id": "sea-chess_2", "type": "feed", "url": "https://a.org"}"#,
+        )
+        .unwrap();
+        assert!(body.apply(None).is_ok());
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use crate::fetch::{Fetch, HttpFetcher};
+use crate::journal::Snapshot;
+use crate::manifest::Manifest;
+use crate::store;
+use clap::Parser;
+use console::Term;
+use curator_sketch::history;
+use curator_sketch::inbox::{self, Item};
+use curator_sketch::source::{self, Source};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum Subcommand {
+    /// Imports feed subscriptions into the source store.
+    Import(Import),
+    /// Exports the feed sources.
+    Export(Export),
+    /// Fetches every feed source and adds its new entries to the inbox.
+    Poll(Poll),
+}
+
+#[derive(Debug, Parser)]
+pub struct Import {
+    /// The format of the file to import.
+    #[clap(long, short = 'f', default_value = "opml", value_parser = ["opml"])]
+    format: String,
+    /// The file to read the subscriptions from.
+    file: PathBuf,
+}
+
+impl Import {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let _lock = manifest.lock()?;
+        let path = manifest.sources_path()?;
+        let snapshot = Snapshot::take(&[path])?;
+        let mut sources = store::read_optional(path, source::from_reader)?;
+        let mut incoming: Vec<Source> = store::read(&self.file, source::from_opml)?
+            .into_iter()
+            .filter(|src| !sources.iter().any(|x| x.url() == src.url()))
+            .collect();
+        source::dedup_ids(&mut incoming, &sources);
+        let imported = incoming.len();
+
+        sources.extend(incoming);
+        store::replace(path, |file| Ok(source::to_writer(&sources, file)?))?;
+
+        println!("Imported {} feed sources", imported);
+
+        snapshot.commit(&manifest, &format!("sources: import {} feeds", imported))
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Export {
+    /// The format of the exported subscriptions.
+    #[clap(long, short = 'f', default_value = "opml", value_parser = ["opml"])]
+    format: String,
+    /// The file to write to. Defaults to the standard output.
+    file: Option<PathBuf>,
+}
+
+impl Export {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let feeds: Vec<Source> = store::read(manifest.sources_path()?, source::from_reader)?
+            .into_iter()
+            .filter(|src| src.type_() == "feed")
+            .collect();
+
+        match &self.file {
+            Some(path) => source::to_opml(&feeds, "Curator feeds", fs::File::create(path)?)?,
+            None => source::to_opml(&feeds, "Curator feeds", Term::stdout())?,
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Poll {
+    /// Only polls the source with this id.
+    id: Option<String>,
+}
+
+impl Poll {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let inbox_path = manifest.inbox_path()?;
+        let mut sources = store::read(manifest.sources_path()?, source::from_reader)?;
+        if let Some(id) = &self.id {
+            sources.retain(|src| src.id() == id);
+            if sources.is_empty() {
+                return Err(Error::usage(format!("No source with id {}", id)));
+            }
+        }
+        let inbox = store::read_optional(inbox_path, inbox::from_reader)?;
+        let known: HashSet<String> = store::read(manifest.history_path()?, history::from_reader)?
+            .iter()
+            .map(|record| record.url().to_string())
+            .collect();
+
+        let (items, failures) = poll(&HttpFetcher::new(), &sources, &inbox, &known);
+
+        for (id, err) in &failures {
+            eprintln!("{}: {}", id, err);
+        }
+
+        // Fetching can be slow, so the lock is only taken to merge the new
+        // entries into the inbox as it is now.
+        let _lock = manifest.lock()?;
+        let snapshot = Snapshot::take(&[inbox_path])?;
+        let mut inbox = store::read_optional(inbox_path, inbox::from_reader)?;
+        let items: Vec<Item> = items
+            .into_iter()
+            .filter(|item| !inbox.iter().any(|x| x.same_entry(item)))
+            .collect();
+        let added = items.len();
+        inbox.extend(items);
+        store::replace(inbox_path, |file| Ok(inbox::to_writer(&inbox, file)?))?;
+
+        println!(
+            "Added {} new entries to the inbox, {} feeds failed",
+            added,
+            failures.len()
+        );
+
+        snapshot.commit(
+            &manifest,
+            &format!("inbox: add {} entries from feeds", added),
+        )
+    }
+}
+
+/// Collects the entries of every feed source not yet in the inbox nor in
+/// the history.
+///
+/// A feed that fails to fetch or parse is reported without stopping the
+/// rest.
+fn poll<F: Fetch>(
+    fetcher: &F,
+    sources: &[Source],
+    inbox: &[Item],
+    known: &HashSet<String>,
+) -> (Vec<Item>, Vec<(String, Error)>) {
+    let mut items: Vec<Item> = Vec::new();
+    let mut failures = Vec::new();
+
+    for src in sources.iter().filter(|src| src.type_() == "feed") {
+        let entries = fetcher
+            .fetch(src.url())
+            .and_then(|bytes| Ok(inbox::from_feed(src.id(), &bytes[..])?));
+
+        match entries {
+            Ok(entries) => {
+                for entry in entries {
+                    let seen = inbox
+                        .iter()
+                        .chain(items.iter())
+                        .any(|x| x.same_entry(&entry));
+
+                    if !seen && !known.contains(entry.url()) {
+                        items.push(entry);
+                    }
+                }
+            }
+            Err(err) => failures.push((src.id().to_string(), err)),
+        }
+    }
+
+    (items, failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Stub(HashMap<&'static str, &'static str>);
+
+    impl Fetch for Stub {
+        fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+            self.0
+                .get(url)
+                .map(|body| body.as_bytes().to_vec())
+                .ok_or_else(|| Error::unavailable("not found"))
+        }
+    }
+
+    const FEED: &str = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>A</title>
+        <item><title>One</title><link>https://a.org/1</link><guid>1</guid></item>
+        <item><title>Two</title><link>https://a.org/2</link><guid>2</guid></item>
+        <item><title>Three</title><link>https://a.org/3</link><guid>3</guid></item>
+        </channel></rss>"#;
+
+    #[test]
+    fn poll_new_entries() {
+        let fetcher = Stub(vec![("https://a.org/feed", FEED)].into_iter().collect());
+        let sources = vec![
+            Source::new("a", "feed", "https://a.org/feed", ""),
+            Source::new("b", "feed", "https://b.org/feed", ""),
+            Source::new("c", "blog", "https://c.org", ""),
+        ];
+        let inbox = vec![Item::new("https://a.org/1").with_id("1").with_origin("a")];
+        let known = vec!["https://a.org/2".to_string()].into_iter().collect();
+
+        let (items, failures) = poll(&fetcher, &sources, &inbox, &known);
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id(), "3");
+        assert_eq!(items[0].origin(), Some("a"));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "b");
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use crate::history;
+use crate::ideas;
+use crate::manifest::Manifest;
+use crate::store;
+use chrono::prelude::*;
+use chrono::Duration;
+use clap::Parser;
+use console::Style;
+use curator_sketch::history::Record;
+use curator_sketch::idea::{Idea, Status};
+use curator_sketch::source;
+use curator_sketch::suggest;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// The width of the longest bar in the terminal charts.
+const BAR_WIDTH: usize = 40;
+
+/// The days compared by the tag trends.
+const TREND_DAYS: i64 = 90;
+
+#[derive(Debug, Parser)]
+pub struct Stats {
+    #[clap(long, short = 'f', default_value = "term", value_parser = ["term", "json"])]
+    format: String,
+    /// The number of entries to show in the rankings.
+    #[clap(long, short = 'n', default_value = "10")]
+    top: usize,
+}
+
+impl Stats {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let records = match manifest.history_path() {
+            Ok(path) => history::load(path)?,
+            Err(_) => Vec::new(),
+        };
+        let ideas = match manifest.ideas_path() {
+            Ok(path) => ideas::load(path)?,
+            Err(_) => Vec::new(),
+        };
+        let sources = match manifest.sources_path() {
+            Ok(path) => store::read_optional(path, source::from_reader)?,
+            Err(_) => Vec::new(),
+        };
+        let known: Vec<&str> = sources.iter().map(|src| src.id()).collect();
+        let report = Report::new(
+            &records,
+            &ideas,
+            &known,
+            manifest.settings().today(),
+            self.top,
+        );
+
+        match &self.format[..] {
+            "json" => {
+                let json = serde_json::to_string_pretty(&report)
+                    .map_err(|err| Error::from(err.to_string()))?;
+                println!("{}", json);
+            }
+            _ => report.print(),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Count {
+    key: String,
+    count: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Trend {
+    tag: String,
+    /// Records in the last `TREND_DAYS` days.
+    recent: usize,
+    /// Records in the `TREND_DAYS` days before.
+    previous: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct IdeaAge {
+    age: String,
+    with_reminder: usize,
+    without_reminder: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    records: usize,
+    months: Vec<Count>,
+    tags: Vec<Count>,
+    trends: Vec<Trend>,
+    origins: Vec<Count>,
+    domains: Vec<Count>,
+    ideas: usize,
+    idea_status: Vec<Count>,
+    idea_ages: Vec<IdeaAge>,
+}
+
+impl Report {
+    fn new(
+        records: &[Record],
+        ideas: &[Idea],
+        sources: &[&str],
+        today: NaiveDate,
+        top: usize,
+    ) -> Self {
+        let mut months = BTreeMap::new();
+        let mut tags = HashMap::new();
+        let mut origins = HashMap::new();
+        let mut domains = HashMap::new();
+        let mut recent = HashMap::new();
+        let mut previous = HashMap::new();
+        let trend_start = today - Duration::days(TREND_DAYS);
+        let previous_start = trend_start - Duration::days(TREND_DAYS);
+
+        for record in records {
+            *months
+                .entry(record.date().format("%Y-%m").to_string())
+                .or_insert(0) += 1;
+
+            let origin = match record.origin() {
+                Some(id) if sources.contains(&id.as_str()) => id,
+                Some(id) => format!("{} (unknown)", id),
+                None => "(none)".to_string(),
+            };
+            *origins.entry(origin).or_insert(0) += 1;
+
+            if let Some(domain) = suggest::domain(record.url()) {
+                *domains.entry(domain.to_lowercase()).or_insert(0) += 1;
+            }
+
+            for tag in record.tags() {
+                if record.date() > trend_start {
+                    *recent.entry(tag.clone()).or_insert(0) += 1;
+                } else if record.date() > previous_start {
+                    *previous.entry(tag.clone()).or_insert(0) += 1;
+                }
+
+                *tags.entry(tag).or_insert(0) += 1;
+            }
+        }
+
+        let tags = ranked(tags, top);
+        let trends = tags
+            .iter()
+            .map(|count| Trend {
+                tag: count.key.clone(),
+                recent: recent.get(&count.key).copied().unwrap_or(0),
+                previous: previous.get(&count.key).copied().unwrap_or(0),
+            })
+            .collect();
+
+        let mut idea_status = Vec::new();
+        for status in &[Status::Open, Status::Done, Status::Dropped] {
+            idea_status.push(Count {
+                key: status.to_string(),
+                count: ideas.iter().filter(|idea| idea.status() == *status).count(),
+            });
+        }
+
+        let mut idea_ages: Vec<IdeaAge> = AGES
+            .iter()
+            .map(|(age, _)| IdeaAge {
+                age: age.to_string(),
+                with_reminder: 0,
+                without_reminder: 0,
+            })
+            .collect();
+        for idea in ideas {
+            let days = NaiveDate::parse_from_str(idea.date(), "%F")
+                .map(|date| (today - date).num_days())
+                .unwrap_or(i64::MAX);
+            let idx = AGES
+                .iter()
+                .position(|(_, limit)| days < *limit)
+                .unwrap_or(AGES.len() - 1);
+
+            if idea.reminder().is_some() {
+                idea_ages[idx].with_reminder += 1;
+            } else {
+                idea_ages[idx].without_reminder += 1;
+            }
+        }
+
+        Report {
+            records: records.len(),
+            months: months
+                .into_iter()
+                .map(|(key, count)| Count { key, count })
+                .collect(),
+            tags,
+            trends,
+            origins: ranked(origins, top),
+            domains: ranked(domains, top),
+            ideas: ideas.len(),
+            idea_status,
+            idea_ages,
+        }
+    }
+
+    fn print(&self) {
+        let bold = Style::new().bold();
+
+        println!("{}", bold.apply_to(format!("Records ({})", self.records)));
+        chart(&self.months);
+
+        println!("\n{}", bold.apply_to("Top tags"));
+        chart(&self.tags);
+
+        println!(
+            "\n{}",
+            bold.apply_to(format!(
+                "Tag trends (last {} days against the previous)",
+                TREND_DAYS
+            ))
+        );
+        for trend in &self.trends {
+            let arrow = match trend.recent.cmp(&trend.previous) {
+                std::cmp::Ordering::Greater => Style::new().green().apply_to("↑"),
+                std::cmp::Ordering::Less => Style::new().red().apply_to("↓"),
+                std::cmp::Ordering::Equal => Style::new().dim().apply_to("="),
+            };
+            println!(
+                "{:20}  {} {:>4}  {:>4}",
+                trend.tag, arrow, trend.recent, trend.previous
+            );
+        }
+
+        println!("\n{}", bold.apply_to("Records per origin"));
+        chart(&self.origins);
+
+        println!("\n{}", bold.apply_to("Top domains"));
+        chart(&self.domains);
+
+        println!("\n{}", bold.apply_to(format!("Ideas ({})", self.ideas)));
+        chart(&self.idea_status);
+
+        println!(
+            "\n{:20}  {:>8}  {:>8}",
+            bold.apply_to("Idea age"),
+            "reminder",
+            "none"
+        );
+        for age in &self.idea_ages {
+            println!(
+                "{:20}  {:>8}  {:>8}",
+                age.age, age.with_reminder, age.without_reminder
+            );
+        }
+    }
+}
+
+/// The idea age buckets with their upper limit in days.
+const AGES: &[(&str, i64)] = &[
+    ("this week", 7),
+    ("this month", 31),
+    ("this year", 366),
+    ("older", i64::MAX),
+];
+
+/// The `top` entries with the highest count, ties sorted by key.
+fn ranked(counts: HashMap<String, usize>, top: usize) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts
+        .into_iter()
+        .map(|(key, count)| Count { key, count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    counts.truncate(top);
+
+    counts
+}
+
+fn chart(counts: &[Count]) {
+    let max = counts.iter().map(|c| c.count).max().unwrap_or(0);
+    let bar = Style::new().cyan();
+
+    for count in counts {
+        // Every count is 0 on an empty cellar, e.g. the idea statuses.
+        let width = match max {
+            0 => 0,
+            max => (count.count * BAR_WIDTH).div_ceil(max),
+        };
+        println!(
+            "{:20}  {:>5}  {}",
+            count.key,
+            count.count,
+            bar.apply_to("█".repeat(width))
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_counts() {
+        let today = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+        let records = vec![
+            Record::new("https://www.a.org/1")
+                .with_date(NaiveDate::from_ymd_opt(2020, 9, 20).unwrap())
+                .with_title("t")
+                .with_summary("s")
+                .with_tags(&["rust", "cli"])
+                .with_origin("a")
+                .build()
+                .unwrap(),
+            Record::new("https://a.org/2")
+                .with_date(NaiveDate::from_ymd_opt(2020, 9, 1).unwrap())
+                .with_title("t")
+                .with_summary("s")
+                .with_tags(&["rust"])
+                .with_origin("x")
+                .build()
+                .unwrap(),
+            Record::new("http://b.org:8080/")
+                .with_date(NaiveDate::from_ymd_opt(2020, 5, 1).unwrap())
+                .with_title("t")
+                .with_summary("s")
+                .with_tags(&["rust"])
+                .build()
+                .unwrap(),
+        ];
+        let ideas = vec![
+            Idea::new("one").with_date(today),
+            Idea::new("two")
+                .with_date(NaiveDate::from_ymd_opt(2019, 1, 1).unwrap())
+                .with_reminder(Some("P1D".to_string())),
+        ];
+        let report = Report::new(&records, &ideas, &["a"], today, 10);
+
+        assert_eq!(report.months.len(), 2);
+        assert_eq!(
+            report.tags[0],
+            Count {
+                key: "rust".to_string(),
+                count: 3
+            }
+        );
+        assert_eq!(
+            report.trends[0],
+            Trend {
+                tag: "rust".to_string(),
+                recent: 2,
+                previous: 1
+            }
+        );
+        assert_eq!(
+            report.domains[0],
+            Count {
+                key: "a.org".to_string(),
+                count: 2
+            }
+        );
+        assert_eq!(report.domains[1].key, "b.org");
+        assert!(report.origins.contains(&Count {
+            key: "x (unknown)".to_string(),
+            count: 1
+        }));
+        assert_eq!(report.idea_ages[0].without_reminder, 1);
+        assert_eq!(report.idea_ages[3].with_reminder, 1);
+    }
+
+    #[test]
+    fn empty_report() {
+        let today = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+        let report = Report::new(&[], &[], &[], today, 10);
+
+        assert_eq!(report.idea_status.len(), 3);
+        assert!(report.idea_status.iter().all(|count| count.count == 0));
+        report.print();
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The lock file at the root of a cellar.
+pub const LOCK_NAME: &str = ".curator.lock";
+
+/// An exclusive advisory lock on a cellar, released when dropped.
+#[derive(Debug)]
+pub struct Lock(File);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Locks the cellar at `root`, waiting up to `timeout` for other writers to
+/// finish.
+pub fn lock(root: &Path, timeout: Duration) -> Result<Lock, Error> {
+    let path = root.join(LOCK_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|err| Error::from(err).in_file(&path))?;
+    let start = Instant::now();
+
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Lock(file)),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(100))
+            }
+            Err(TryLockError::WouldBlock) => return Err(Error::busy(format!(
+                "The cellar at {} is busy",
+                root.display()
+            ))
+            .with_hint(
+                "Another curator process is changing it. Try again or pass a longer --lock-timeout",
+            )),
+            Err(TryLockError::Error(err)) => return Err(Error::from(err).in_file(&path)),
+        }
+    }
+}
+
+/// Reads a file with `parse`, attributing any failure to its path.
+pub fn read<T, E, F>(path: &Path, parse: F) -> Result<T, Error>
+where
+    F: FnOnce(File) -> Result<T, E>,
+    Error: From<E>,
+{
+    parse(open(path)?).map_err(|err| Error::from(err).in_file(path))
+}
+
+/// Reads a store with `parse`, as empty when it doesn't exist yet.
+pub fn read_optional<T, E, F>(path: &Path, parse: F) -> Result<T, Error>
+where
+    T: Default,
+    F: FnOnce(File) -> Result<T, E>,
+    Error: From<E>,
+{
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    read(path, parse)
+}
+
+fn open(path: &Path) -> Result<File, Error> {
+    File::open(path).map_err(|err| Error::from(err).in_file(path))
+}
+
+/// Creates an empty store with the given headers unless it already exists.
+///
+/// Returns whether the store was created.
+pub fn create(path: &Path, headers: &[&str]) -> Result<bool, Error> {
+    if path.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut wtr = csv::Writer::from_writer(File::create(path)?);
+    wtr.write_record(headers)?;
+    wtr.flush()?;
+
+    Ok(true)
+}
+
+/// Rewrites a store through a temporary sibling file so a failure never
+/// leaves a half-written store behind.
+pub fn replace<F>(path: &Path, write: F) -> Result<(), Error>
+where
+    F: FnOnce(File) -> Result<(), Error>,
+{
+    let tmp = path.with_extension("tmp");
+
+    write(File::create(&tmp)?)?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_cellar() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        let held = lock(root, Duration::ZERO)?;
+        let busy = lock(root, Duration::from_millis(200)).unwrap_err();
+        drop(held);
+        let free = lock(root, Duration::ZERO);
+
+        assert_eq!(busy.exit_code(), 75);
+        assert!(free.is_ok());
+
+        Ok(())
+    }
+}
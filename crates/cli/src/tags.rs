@@ -0,0 +1,119 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::error::Error;
+use crate::history;
+use crate::manifest::Manifest;
+use crate::store;
+use clap::Parser;
+use curator_sketch::history::Record;
+use curator_sketch::suggest::{self, Model};
+use std::fs::File;
+
+/// The tag model cached at the root of a cellar.
+pub const MODEL_NAME: &str = ".curator.tags";
+
+/// The number of tags suggested by default.
+pub const SUGGESTED: usize = 3;
+
+#[derive(Debug, Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum Subcommand {
+    /// Suggests tags for a resource, learned from the history.
+    Suggest(Suggest),
+}
+
+#[derive(Debug, Parser)]
+pub struct Suggest {
+    /// The URL of the resource to tag.
+    #[clap(required_unless_present = "retrain")]
+    url: Option<String>,
+    /// The title of the resource, if not in the history.
+    #[clap(long)]
+    title: Option<String>,
+    /// The summary of the resource, if not in the history.
+    #[clap(long)]
+    summary: Option<String>,
+    /// The maximum number of tags to suggest.
+    #[clap(long, short = 'n', default_value_t = SUGGESTED)]
+    limit: usize,
+    /// Rebuilds the model from the history first.
+    #[clap(long)]
+    retrain: bool,
+}
+
+impl Suggest {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let records = history::load(manifest.history_path()?)?;
+        let model = if self.retrain {
+            let model = train(&manifest, &records)?;
+            eprintln!(
+                "Trained on {} records, {} tags",
+                model.records(),
+                model.tags()
+            );
+            model
+        } else {
+            model(&manifest, &records)?
+        };
+
+        let url = match &self.url {
+            Some(url) => url,
+            None => return Ok(()),
+        };
+        let record = records.iter().find(|record| record.url() == url);
+        let title = self
+            .title
+            .as_deref()
+            .or_else(|| record.map(|r| r.title()))
+            .unwrap_or("");
+        let summary = self
+            .summary
+            .as_deref()
+            .or_else(|| record.map(|r| r.summary()))
+            .unwrap_or("");
+
+        for (tag, probability) in model.suggest(url, title, summary, self.limit) {
+            println!("{:.2}  {}", probability, tag);
+        }
+
+        Ok(())
+    }
+}
+
+/// The cached model, retrained when missing, unreadable or trained on a
+/// different history.
+pub fn model(manifest: &Manifest, records: &[Record]) -> Result<Model, Error> {
+    let path = manifest.root().join(MODEL_NAME);
+    let cached = File::open(&path)
+        .ok()
+        .and_then(|file| suggest::from_reader(file).ok());
+
+    match cached {
+        Some(model) if model.is_trained_on(records) => Ok(model),
+        _ => train(manifest, records),
+    }
+}
+
+/// Trains the model on the records and caches it.
+///
+/// The cache is replaced atomically, as read-only commands write it too and
+/// don't hold the cellar lock.
+fn train(manifest: &Manifest, records: &[Record]) -> Result<Model, Error> {
+    let path = manifest.root().join(MODEL_NAME);
+    let model = Model::train(records);
+    store::replace(&path, |file| {
+        suggest::to_writer(&model, file).map_err(|err| Error::from(err.to_string()))
+    })
+    .map_err(|err| err.in_file(&path))?;
+
+    Ok(model)
+}
@@ -0,0 +1,745 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! A full-screen browser for the history, idea and source stores.
+
+use crate::browser;
+use crate::error::Error;
+use crate::git;
+use crate::history::{self as history_cmd, prompt_record};
+use crate::ideas;
+use crate::journal::Snapshot;
+use crate::manifest::Manifest;
+use crate::store;
+use crate::tags;
+use clap::Parser;
+use curator_sketch::annotation::Annotation;
+use curator_sketch::history::Record;
+use curator_sketch::idea::Idea;
+use curator_sketch::source::{self, Source};
+use curator_sketch::suggest::Model;
+use curator_sketch::text::Text;
+use tuikit::prelude::{
+    Attr, Canvas, Draw, Effect, Event, HSplit, Key, Result as DrawResult, Size, Term, VSplit,
+    Widget, Win,
+};
+
+const HELP: &str = "tab pane  j/k move  / filter  a add  e edit  d delete  o open  q quit";
+
+#[derive(Debug, Parser)]
+pub struct Tui;
+
+impl Tui {
+    pub fn run(&self, manifest: Manifest) -> Result<(), Error> {
+        let mut app = App::new(&manifest)?;
+        let term = Term::new().map_err(tui_error)?;
+
+        loop {
+            term.draw(&Screen(&app)).map_err(tui_error)?;
+            term.present().map_err(tui_error)?;
+
+            if let Event::Key(key) = term.poll_event().map_err(tui_error)? {
+                if !app.handle(key, &term) {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    History,
+    Ideas,
+    Sources,
+}
+
+impl Pane {
+    const ALL: [Pane; 3] = [Pane::History, Pane::Ideas, Pane::Sources];
+
+    fn title(self) -> &'static str {
+        match self {
+            Pane::History => "History",
+            Pane::Ideas => "Ideas",
+            Pane::Sources => "Sources",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Browse,
+    /// Typing the filter query.
+    Filter,
+    /// Typing the URL of a new record.
+    Add(String),
+    /// Waiting for the deletion to be confirmed.
+    Delete,
+}
+
+struct App<'a> {
+    manifest: &'a Manifest,
+    pane: Pane,
+    mode: Mode,
+    query: String,
+    selected: usize,
+    message: Option<String>,
+    records: Vec<Record>,
+    /// The extracted text of the records, searched by the filter too.
+    texts: Vec<Text>,
+    /// The passages kept from the records, searched by the filter too.
+    annotations: Vec<Annotation>,
+    /// Suggests the tags of new and edited records.
+    model: Model,
+    ideas: Vec<Idea>,
+    sources: Vec<Source>,
+}
+
+impl<'a> App<'a> {
+    fn new(manifest: &'a Manifest) -> Result<App<'a>, Error> {
+        let mut app = App {
+            manifest,
+            pane: Pane::History,
+            mode: Mode::Browse,
+            query: String::new(),
+            selected: 0,
+            message: None,
+            records: Vec::new(),
+            texts: Vec::new(),
+            annotations: Vec::new(),
+            model: Model::default(),
+            ideas: Vec::new(),
+            sources: Vec::new(),
+        };
+        app.reload()?;
+
+        Ok(app)
+    }
+
+    /// Reads the stores again, skipping the disabled ones.
+    fn reload(&mut self) -> Result<(), Error> {
+        self.records = match self.manifest.history_path() {
+            Ok(path) => history_cmd::load(path)?,
+            Err(_) => Vec::new(),
+        };
+        self.texts = history_cmd::load_texts(self.manifest)?;
+        self.annotations = history_cmd::load_annotations(self.manifest)?;
+        self.model = tags::model(self.manifest, &self.records)?;
+        self.ideas = match self.manifest.ideas_path() {
+            Ok(path) => ideas::load(path)?,
+            Err(_) => Vec::new(),
+        };
+        self.sources = match self.manifest.sources_path() {
+            Ok(path) => store::read_optional(path, source::from_reader)?,
+            Err(_) => Vec::new(),
+        };
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+
+        Ok(())
+    }
+
+    /// Indexes of the entries in the current pane matching the query.
+    fn rows(&self) -> Vec<usize> {
+        match self.pane {
+            Pane::History => filtered(&self.records, |record| {
+                matches(
+                    &self.query,
+                    &history_cmd::searched(record, &self.texts, &self.annotations),
+                    &record.tags(),
+                    record.origin().as_deref(),
+                )
+            }),
+            Pane::Ideas => filtered(&self.ideas, |idea| {
+                matches(&self.query, &[idea.content()], idea.tags(), None)
+            }),
+            Pane::Sources => filtered(&self.sources, |src| {
+                matches(
+                    &self.query,
+                    &[src.id(), src.url(), src.comment()],
+                    &[],
+                    Some(src.type_()),
+                )
+            }),
+        }
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.rows().get(self.selected).copied()
+    }
+
+    fn line(&self, idx: usize) -> String {
+        match self.pane {
+            Pane::History => {
+                let record = &self.records[idx];
+                format!("{}  {}", record.date(), record.title())
+            }
+            Pane::Ideas => {
+                let idea = &self.ideas[idx];
+                format!("{}  {:7}  {}", idea.date(), idea.status(), idea.summary())
+            }
+            Pane::Sources => {
+                let src = &self.sources[idx];
+                format!("{:16}  {:6}  {}", src.id(), src.type_(), src.url())
+            }
+        }
+    }
+
+    fn preview(&self) -> String {
+        let idx = match self.current() {
+            Some(idx) => idx,
+            None => return String::new(),
+        };
+
+        match self.pane {
+            Pane::History => {
+                let record = &self.records[idx];
+                format!(
+                    "{}\n{}\n\n{}  {}\ntags: {}\n\n{}",
+                    record.title(),
+                    record.url(),
+                    record.date(),
+                    record.origin().unwrap_or_default(),
+                    record.tags().join(", "),
+                    record.summary()
+                )
+            }
+            Pane::Ideas => self.ideas[idx]
+                .to_markdown()
+                .unwrap_or_else(|err| err.to_string()),
+            Pane::Sources => {
+                let src = &self.sources[idx];
+                format!(
+                    "{}\n{}\n\ntype: {}\n\n{}",
+                    src.id(),
+                    src.url(),
+                    src.type_(),
+                    src.comment()
+                )
+            }
+        }
+    }
+
+    /// Handles a key press, returning whether to keep running.
+    fn handle(&mut self, key: Key, term: &Term) -> bool {
+        match self.mode.clone() {
+            Mode::Filter => match key {
+                Key::Enter => self.mode = Mode::Browse,
+                Key::ESC => {
+                    self.query.clear();
+                    self.mode = Mode::Browse;
+                }
+                Key::Backspace => {
+                    self.query.pop();
+                }
+                Key::Char(c) => self.query.push(c),
+                _ => {}
+            },
+            Mode::Add(mut url) => match key {
+                Key::Enter => {
+                    self.mode = Mode::Browse;
+                    let result = suspended(term, || self.add(&url));
+                    self.report(result);
+                }
+                Key::ESC => self.mode = Mode::Browse,
+                Key::Backspace => {
+                    url.pop();
+                    self.mode = Mode::Add(url);
+                }
+                Key::Char(c) => {
+                    url.push(c);
+                    self.mode = Mode::Add(url);
+                }
+                _ => {}
+            },
+            Mode::Delete => {
+                self.mode = Mode::Browse;
+
+                if key == Key::Char('y') {
+                    let result = self.delete();
+                    self.report(result);
+                } else {
+                    self.message = None;
+                }
+            }
+            Mode::Browse => {
+                self.message = None;
+
+                match key {
+                    Key::Char('q') | Key::Ctrl('c') => return false,
+                    Key::Tab | Key::BackTab => {
+                        let idx = Pane::ALL.iter().position(|p| *p == self.pane).unwrap_or(0);
+                        let step = if key == Key::Tab {
+                            1
+                        } else {
+                            Pane::ALL.len() - 1
+                        };
+                        self.pane = Pane::ALL[(idx + step) % Pane::ALL.len()];
+                        self.selected = 0;
+                    }
+                    Key::Char('j') | Key::Down => {
+                        self.selected = (self.selected + 1).min(self.rows().len().saturating_sub(1))
+                    }
+                    Key::Char('k') | Key::Up => self.selected = self.selected.saturating_sub(1),
+                    Key::Char('g') | Key::Home => self.selected = 0,
+                    Key::Char('G') | Key::End => {
+                        self.selected = self.rows().len().saturating_sub(1)
+                    }
+                    Key::Char('/') => {
+                        self.mode = Mode::Filter;
+                        self.selected = 0;
+                    }
+                    Key::Char('a') => self.mode = Mode::Add(String::new()),
+                    Key::Char('e') => {
+                        let result = suspended(term, || self.edit());
+                        self.report(result);
+                    }
+                    Key::Char('d') if self.current().is_some() => {
+                        self.mode = Mode::Delete;
+                        self.message = Some("Delete the selected entry? (y/n)".to_string());
+                    }
+                    Key::Char('o') => {
+                        let result = self.open();
+                        self.report(result);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Shows the outcome of an action in the status bar.
+    fn report(&mut self, result: Result<String, Error>) {
+        let result = self.reload().and(result);
+
+        self.message = match result {
+            Ok(message) if message.is_empty() => None,
+            Ok(message) => Some(message),
+            Err(err) => Some(err.to_string()),
+        };
+    }
+
+    fn add(&mut self, url: &str) -> Result<String, Error> {
+        let settings = self.manifest.settings();
+        let builder = settings.draft(Record::new(url));
+        let record = prompt_record(builder, &self.sources, &self.records, &self.model, settings)?;
+        let path = self.manifest.history_path()?;
+        let _lock = self.manifest.lock()?;
+        let snapshot = Snapshot::take(&[path])?;
+
+        vacant(&history_cmd::load(path)?, Record::url, record.url())
+            .map_err(|err| err.in_file(path))?;
+        record.append_into(path)?;
+        snapshot.commit(
+            self.manifest,
+            &format!("history: add {}", git::subject(record.title())),
+        )?;
+
+        Ok(format!("Added {}", record.url()))
+    }
+
+    fn edit(&mut self) -> Result<String, Error> {
+        let idx = match self.current() {
+            Some(idx) => idx,
+            None => return Ok(String::new()),
+        };
+        let settings = self.manifest.settings();
+
+        match self.pane {
+            Pane::History => {
+                let original = &self.records[idx];
+                let record = prompt_record(
+                    original.to_builder(),
+                    &self.sources,
+                    &self.records,
+                    &self.model,
+                    settings,
+                )?;
+                let path = self.manifest.history_path()?;
+                let _lock = self.manifest.lock()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut records = history_cmd::load(path)?;
+
+                replace(&mut records, Record::url, original.url(), record.clone())
+                    .map_err(|err| err.in_file(path))?;
+                history_cmd::save(path, &records)?;
+                snapshot.commit(
+                    self.manifest,
+                    &format!("history: edit {}", git::subject(record.title())),
+                )?;
+
+                Ok(format!("Edited {}", record.url()))
+            }
+            Pane::Ideas => {
+                let original = &self.ideas[idx];
+                let text = settings
+                    .editor()
+                    .extension(".md")
+                    .edit(&original.to_markdown()?)?
+                    .ok_or_else(Error::aborted)?;
+                let idea = Idea::from_markdown(&text)?;
+                let path = self.manifest.ideas_path()?;
+                let _lock = self.manifest.lock()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut ideas = ideas::load(path)?;
+
+                replace(&mut ideas, Idea::id, original.id(), idea.clone())
+                    .map_err(|err| err.in_file(path))?;
+                ideas::save(path, &ideas)?;
+                snapshot.commit(
+                    self.manifest,
+                    &format!("ideas: edit {}", git::subject(idea.summary())),
+                )?;
+
+                Ok(format!("Edited idea {}", idea.id()))
+            }
+            Pane::Sources => {
+                let original = &self.sources[idx];
+                let text = settings
+                    .editor()
+                    .extension(".toml")
+                    .edit(&toml::to_string(original)?)?
+                    .ok_or_else(Error::aborted)?;
+                let src: Source = toml::from_str(&text)?;
+                source::check_id(src.id())?;
+                let path = self.manifest.sources_path()?;
+                let _lock = self.manifest.lock()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut sources = store::read(path, source::from_reader)?;
+
+                replace(&mut sources, Source::id, original.id(), src.clone())
+                    .map_err(|err| err.in_file(path))?;
+                store::replace(path, |file| Ok(source::to_writer(&sources, file)?))?;
+                snapshot.commit(self.manifest, &format!("sources: edit {}", src.id()))?;
+
+                Ok(format!("Edited source {}", src.id()))
+            }
+        }
+    }
+
+    fn delete(&mut self) -> Result<String, Error> {
+        let idx = match self.current() {
+            Some(idx) => idx,
+            None => return Ok(String::new()),
+        };
+        let _lock = self.manifest.lock()?;
+
+        match self.pane {
+            Pane::History => {
+                let url = self.records[idx].url().to_string();
+                let path = self.manifest.history_path()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut records = history_cmd::load(path)?;
+
+                records.retain(|x| x.url() != url);
+                history_cmd::save(path, &records)?;
+                snapshot.commit(self.manifest, &format!("history: delete {}", url))?;
+
+                Ok(format!("Deleted {}", url))
+            }
+            Pane::Ideas => {
+                let id = self.ideas[idx].id().to_string();
+                let path = self.manifest.ideas_path()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut ideas = ideas::load(path)?;
+
+                ideas.retain(|x| x.id() != id);
+                ideas::save(path, &ideas)?;
+                snapshot.commit(self.manifest, &format!("ideas: delete {}", id))?;
+
+                Ok(format!("Deleted idea {}", id))
+            }
+            Pane::Sources => {
+                let id = self.sources[idx].id().to_string();
+                let path = self.manifest.sources_path()?;
+                let snapshot = Snapshot::take(&[path])?;
+                let mut sources = store::read(path, source::from_reader)?;
+
+                sources.retain(|x| x.id() != id);
+                store::replace(path, |file| Ok(source::to_writer(&sources, file)?))?;
+                snapshot.commit(self.manifest, &format!("sources: delete {}", id))?;
+
+                Ok(format!("Deleted source {}", id))
+            }
+        }
+    }
+
+    /// Opens the URL of the selected entry with the system browser.
+    fn open(&self) -> Result<String, Error> {
+        let url = match (self.pane, self.current()) {
+            (Pane::History, Some(idx)) => self.records[idx].url(),
+            (Pane::Sources, Some(idx)) => self.sources[idx].url(),
+            _ => return Err(Error::usage("Nothing to open")),
+        };
+        browser::open(url)?;
+
+        Ok(format!("Opened {}", url))
+    }
+}
+
+/// Hands the terminal over to `action`, e.g. to run the editor.
+fn suspended<F>(term: &Term, action: F) -> Result<String, Error>
+where
+    F: FnOnce() -> Result<String, Error>,
+{
+    term.pause().map_err(tui_error)?;
+    let result = action();
+    term.restart().map_err(tui_error)?;
+
+    result
+}
+
+/// Replaces the entry keyed `original` with its edited version, unless it
+/// is gone since the screen was loaded or the new key belongs to another
+/// entry.
+fn replace<T, F>(entries: &mut [T], key: F, original: &str, edited: T) -> Result<(), Error>
+where
+    F: Fn(&T) -> &str,
+{
+    let idx = entries
+        .iter()
+        .position(|x| key(x) == original)
+        .ok_or_else(|| Error::usage(format!("{} is no longer in the store", original)))?;
+    let new_key = key(&edited);
+
+    if new_key != original {
+        vacant(entries, &key, new_key)?;
+    }
+
+    entries[idx] = edited;
+
+    Ok(())
+}
+
+/// Fails when an entry already has the key.
+fn vacant<T, F>(entries: &[T], key: F, new_key: &str) -> Result<(), Error>
+where
+    F: Fn(&T) -> &str,
+{
+    if entries.iter().any(|x| key(x) == new_key) {
+        return Err(Error::usage(format!("{} is already in the store", new_key)));
+    }
+
+    Ok(())
+}
+
+fn filtered<T, F: Fn(&T) -> bool>(entries: &[T], keep: F) -> Vec<usize> {
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| keep(entry))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Whether an entry matches every word of the query.
+///
+/// `#word` matches the start of a tag, `@word` the start of the origin and
+/// anything else a part of any text, ignoring case.
+fn matches(query: &str, texts: &[&str], tags: &[String], origin: Option<&str>) -> bool {
+    query.split_whitespace().all(|word| {
+        let word = word.to_lowercase();
+
+        if let Some(tag) = word.strip_prefix('#') {
+            tags.iter().any(|t| t.to_lowercase().starts_with(tag))
+        } else if let Some(prefix) = word.strip_prefix('@') {
+            origin.is_some_and(|o| o.to_lowercase().starts_with(prefix))
+        } else {
+            texts.iter().any(|t| t.to_lowercase().contains(&word))
+        }
+    })
+}
+
+fn tui_error(err: Box<dyn std::error::Error>) -> Error {
+    Error::from(format!("Terminal error: {}", err))
+}
+
+/// Splits the text into lines no wider than `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + word.chars().count() + 1 > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+struct Screen<'a, 'b>(&'a App<'b>);
+
+impl Draw for Screen<'_, '_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let app = self.0;
+        let header = Header(app);
+        let list = List(app);
+        let preview = Preview(app);
+        let status = Status(app);
+
+        let layout = VSplit::default()
+            .split(Win::new(&header).basis(1).grow(0).shrink(0))
+            .split(
+                HSplit::default()
+                    .split(Win::new(&list).border(true).basis(Size::Percent(50)))
+                    .split(
+                        Win::new(&preview)
+                            .border(true)
+                            .padding_left(1)
+                            .basis(Size::Percent(50)),
+                    ),
+            )
+            .split(Win::new(&status).basis(1).grow(0).shrink(0));
+
+        layout.draw(canvas)
+    }
+}
+
+impl Widget for Screen<'_, '_> {}
+
+struct Header<'a, 'b>(&'a App<'b>);
+
+impl Draw for Header<'_, '_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let app = self.0;
+        let mut col = 0;
+
+        for pane in Pane::ALL.iter() {
+            let attr = if *pane == app.pane {
+                Effect::REVERSE.into()
+            } else {
+                Attr::default()
+            };
+            col += canvas.print_with_attr(0, col, &format!(" {} ", pane.title()), attr)?;
+            col += 1;
+        }
+
+        if app.mode == Mode::Filter || !app.query.is_empty() {
+            canvas.print(0, col + 1, &format!("/{}", app.query))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Widget for Header<'_, '_> {}
+
+struct List<'a, 'b>(&'a App<'b>);
+
+impl Draw for List<'_, '_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let app = self.0;
+        let (_width, height) = canvas.size()?;
+        let rows = app.rows();
+        let offset = (app.selected + 1).saturating_sub(height);
+
+        for (row, idx) in rows.iter().skip(offset).take(height).enumerate() {
+            let attr = if row + offset == app.selected {
+                Effect::REVERSE.into()
+            } else {
+                Attr::default()
+            };
+            canvas.print_with_attr(row, 0, &app.line(*idx), attr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Widget for List<'_, '_> {}
+
+struct Preview<'a, 'b>(&'a App<'b>);
+
+impl Draw for Preview<'_, '_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let (width, height) = canvas.size()?;
+
+        for (row, line) in wrap(&self.0.preview(), width)
+            .iter()
+            .take(height)
+            .enumerate()
+        {
+            canvas.print(row, 0, line)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Widget for Preview<'_, '_> {}
+
+struct Status<'a, 'b>(&'a App<'b>);
+
+impl Draw for Status<'_, '_> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let app = self.0;
+        let text = match (&app.mode, &app.message) {
+            (Mode::Add(url), _) => format!("URL: {}", url),
+            (_, Some(message)) => message.clone(),
+            _ => HELP.to_string(),
+        };
+
+        canvas.print_with_attr(0, 0, &text, Effect::DIM.into())?;
+
+        Ok(())
+    }
+}
+
+impl Widget for Status<'_, '_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_words() {
+        let tags = vec!["rust".to_string(), "cli".to_string()];
+        let texts = ["Curator", "https://example.org"];
+
+        assert!(matches("", &texts, &tags, None));
+        assert!(matches("cura #ru", &texts, &tags, Some("pinboard")));
+        assert!(matches("@pin EXAMPLE", &texts, &tags, Some("pinboard")));
+        assert!(!matches("#go", &texts, &tags, None));
+        assert!(!matches("@pin", &texts, &tags, None));
+    }
+
+    #[test]
+    fn replace_edited() {
+        let mut records: Vec<Record> = ["https://a.org", "https://b.org"]
+            .iter()
+            .map(|url| {
+                Record::new(*url)
+                    .with_title("t")
+                    .with_summary("s")
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let moved = |url: &str| records[0].to_builder().with_url(url).build().unwrap();
+        let (taken, free) = (moved("https://b.org"), moved("https://c.org"));
+
+        assert!(replace(&mut records, Record::url, "https://a.org", taken).is_err());
+        assert!(replace(&mut records, Record::url, "https://gone.org", free.clone()).is_err());
+        assert!(replace(&mut records, Record::url, "https://a.org", free).is_ok());
+        assert_eq!(records[0].url(), "https://c.org");
+        assert_eq!(records.len(), 2);
+        assert!(vacant(&records, Record::url, "https://b.org").is_err());
+        assert!(vacant(&records, Record::url, "https://a.org").is_ok());
+    }
+}
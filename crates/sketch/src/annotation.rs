@@ -0,0 +1,189 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Passages kept from history records, with an optional note.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The columns of an annotation store.
+pub const HEADERS: &[&str] = &["url", "date", "quote", "note", "location"];
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Annotation {
+    /// The URL of the annotated record.
+    url: String,
+    #[serde(with = "crate::history::date")]
+    date: NaiveDate,
+    quote: String,
+    note: Option<String>,
+    /// Where the quote is in the resource, e.g. a page or a section.
+    location: Option<String>,
+}
+
+impl Annotation {
+    /// An annotation dated today. The quote can't be blank.
+    pub fn new(url: &str, quote: &str) -> Result<Self, AnnotationError> {
+        let quote = quote.trim();
+
+        if quote.is_empty() {
+            return Err(AnnotationError::MissingQuote);
+        }
+
+        Ok(Annotation {
+            url: url.to_string(),
+            date: Utc::now().date_naive(),
+            quote: quote.to_string(),
+            note: None,
+            location: None,
+        })
+    }
+
+    pub fn with_date(mut self, date: NaiveDate) -> Self {
+        self.date = date;
+        self
+    }
+
+    /// Sets the note, leaving it out when blank.
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.note = Some(note.trim().to_string()).filter(|note| !note.is_empty());
+        self
+    }
+
+    /// Sets the location, leaving it out when blank.
+    pub fn with_location(mut self, location: &str) -> Self {
+        self.location = Some(location.trim().to_string()).filter(|loc| !loc.is_empty());
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+}
+
+/// The annotations of the given URL, in the order they were added.
+pub fn of<'a>(annotations: &'a [Annotation], url: &'a str) -> impl Iterator<Item = &'a Annotation> {
+    annotations
+        .iter()
+        .filter(move |annotation| annotation.url == url)
+}
+
+/// Reads the annotations from a CSV annotation store.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Annotation>, AnnotationError> {
+    let mut annotations = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for result in rdr.deserialize() {
+        let annotation: Annotation = result?;
+        annotations.push(annotation);
+    }
+
+    Ok(annotations)
+}
+
+/// Writes the full list of annotations, headers included.
+pub fn to_writer<W: Write>(annotations: &[Annotation], writer: W) -> Result<(), AnnotationError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    wtr.write_record(HEADERS)?;
+
+    for annotation in annotations {
+        wtr.serialize(annotation)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum AnnotationError {
+    MissingQuote,
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationError::MissingQuote => write!(f, "'quote' is a required field"),
+            AnnotationError::Csv(err) => write!(f, "{}", err),
+            AnnotationError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for AnnotationError {}
+
+impl From<io::Error> for AnnotationError {
+    fn from(err: io::Error) -> AnnotationError {
+        AnnotationError::Io(err)
+    }
+}
+
+impl From<csv::Error> for AnnotationError {
+    fn from(err: csv::Error) -> AnnotationError {
+        AnnotationError::Csv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<(), Box<dyn Error>> {
+        let annotations = vec![
+            Annotation::new("https://a.org", " A passage,\nover two lines. ")?
+                .with_note("")
+                .with_location("§2"),
+            Annotation::new("https://b.org", "Other")?,
+            Annotation::new("https://a.org", "Second")?.with_note("Why it matters"),
+        ];
+        let mut buffer = Vec::new();
+        to_writer(&annotations, &mut buffer)?;
+        let actual = from_reader(&buffer[..])?;
+
+        assert_eq!(actual, annotations);
+        assert_eq!(actual[0].quote(), "A passage,\nover two lines.");
+        assert_eq!(actual[0].note(), None);
+        assert_eq!(of(&actual, "https://a.org").count(), 2);
+        assert!(Annotation::new("https://a.org", "  ").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_date() {
+        let store = "url,date,quote,note,location\n\
+                     https://a.org,2020-02-30,A passage,,\n";
+        let actual = from_reader(store.as_bytes()).unwrap_err();
+
+        match actual {
+            AnnotationError::Csv(err) => assert_eq!(err.position().map(|p| p.line()), Some(2)),
+            err => panic!("Expected a CSV error, got {:?}", err),
+        }
+    }
+}
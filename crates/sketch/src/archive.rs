@@ -0,0 +1,149 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! The index of the pages saved offline for history records.
+//!
+//! Archived files are named after the SHA-256 of their content, so the
+//! index only keeps the hash and when the copy was taken.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The columns of an archive index.
+pub const HEADERS: &[&str] = &["url", "hash", "date", "format"];
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Entry {
+    /// The URL of the archived record.
+    url: String,
+    hash: String,
+    /// When the copy was taken, as RFC 3339.
+    date: String,
+    /// Either `html` or `warc`.
+    format: String,
+}
+
+impl Entry {
+    pub fn new(url: &str, hash: &str, format: &str) -> Self {
+        Entry {
+            url: url.to_string(),
+            hash: hash.to_string(),
+            date: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            format: format.to_string(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn date(&self) -> &str {
+        &self.date
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// The name of the archived file within the archive directory.
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.hash, self.format)
+    }
+}
+
+/// The most recent copy of the given URL.
+pub fn latest<'a>(entries: &'a [Entry], url: &str) -> Option<&'a Entry> {
+    entries.iter().rev().find(|entry| entry.url == url)
+}
+
+/// Reads the entries from a CSV archive index.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Entry>, ArchiveError> {
+    let mut entries = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for result in rdr.deserialize() {
+        let entry: Entry = result?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Writes the full list of entries, headers included.
+pub fn to_writer<W: Write>(entries: &[Entry], writer: W) -> Result<(), ArchiveError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    wtr.write_record(HEADERS)?;
+
+    for entry in entries {
+        wtr.serialize(entry)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Csv(err) => write!(f, "{}", err),
+            ArchiveError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> ArchiveError {
+        ArchiveError::Io(err)
+    }
+}
+
+impl From<csv::Error> for ArchiveError {
+    fn from(err: csv::Error) -> ArchiveError {
+        ArchiveError::Csv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_copy() -> Result<(), Box<dyn Error>> {
+        let entries = vec![
+            Entry::new("https://a.org", "aaa", "html"),
+            Entry::new("https://b.org", "bbb", "html"),
+            Entry::new("https://a.org", "ccc", "warc"),
+        ];
+        let mut buffer = Vec::new();
+        to_writer(&entries, &mut buffer)?;
+        let entries = from_reader(&buffer[..])?;
+
+        assert_eq!(
+            latest(&entries, "https://a.org").map(|e| e.file_name()),
+            Some("ccc.warc".to_string())
+        );
+        assert!(latest(&entries, "https://c.org").is_none());
+
+        Ok(())
+    }
+}
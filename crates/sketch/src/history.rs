@@ -6,23 +6,45 @@
 
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::error::Error;
-use std::fs::OpenOptions;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
 use std::path::Path;
+use std::str::FromStr;
 use std::{fmt, io};
 
+/// The columns of a history store.
+pub const HEADERS: &[&str] = &[
+    "date", "url", "title", "summary", "tags", "origin", "related", "status", "rating",
+];
+
+/// The highest rating of a record, the lowest being 1.
+pub const MAX_RATING: u8 = 5;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Record {
-    date: String,
+    /// Parsed when reading so a malformed date is reported with its row.
+    #[serde(with = "date")]
+    date: NaiveDate,
     url: String,
     title: String,
     summary: String,
     #[serde(with = "tags")]
     tags: Vec<String>,
     origin: Option<String>,
+    /// Missing from stores written before records could be linked.
+    #[serde(default, with = "links")]
+    related: Vec<Link>,
+    /// Missing from stores written before records had a reading status.
+    #[serde(default)]
+    status: Option<ReadingStatus>,
+    #[serde(default, with = "rating")]
+    rating: Option<u8>,
 }
 
 impl Record {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<S: Into<String>>(url: S) -> RecordBuilder {
         RecordBuilder::new(url)
     }
@@ -32,7 +54,7 @@ impl Record {
     }
 
     pub fn date(&self) -> NaiveDate {
-        NaiveDate::parse_from_str(&self.date, "%y-%m-%d").expect("A valid date")
+        self.date
     }
 
     pub fn title(&self) -> &str {
@@ -51,6 +73,47 @@ impl Record {
         self.origin.clone()
     }
 
+    /// The records this one links to.
+    pub fn related(&self) -> &[Link] {
+        &self.related
+    }
+
+    /// Links the record to another one, replacing the label of an existing
+    /// link to it.
+    pub fn link(&mut self, link: Link) {
+        match self.related.iter_mut().find(|l| l.url == link.url) {
+            Some(existing) => *existing = link,
+            None => self.related.push(link),
+        }
+    }
+
+    pub fn status(&self) -> Option<ReadingStatus> {
+        self.status
+    }
+
+    pub fn rating(&self) -> Option<u8> {
+        self.rating
+    }
+
+    pub fn set_status(&mut self, status: Option<ReadingStatus>) {
+        self.status = status;
+    }
+
+    /// Sets the rating, from 1 to `MAX_RATING`.
+    pub fn set_rating(&mut self, rating: Option<u8>) -> Result<(), RecordError> {
+        self.rating = rating.map(validate_rating).transpose()?;
+
+        Ok(())
+    }
+
+    /// Removes the link to the given URL, telling whether there was one.
+    pub fn unlink(&mut self, url: &str) -> bool {
+        let len = self.related.len();
+        self.related.retain(|link| link.url != url);
+
+        self.related.len() != len
+    }
+
     pub fn write<W: io::Write>(&self, writer: W) -> Result<(), RecordError> {
         let mut wtr = csv::WriterBuilder::new()
             .has_headers(false)
@@ -61,15 +124,192 @@ impl Record {
         Ok(())
     }
 
+    /// Appends the record to the store, first rewriting a store written
+    /// with older headers.
+    ///
+    /// The rewrite goes through a sibling temporary file renamed over the
+    /// store, so a failure never leaves the history truncated.
     pub fn append_into<P: AsRef<Path>>(&self, path: P) -> Result<(), RecordError> {
-        self.write(OpenOptions::new().append(true).open(path)?)
+        let path = path.as_ref();
+        let mut rdr = csv::Reader::from_path(path)?;
+
+        if rdr.headers()?.iter().eq(HEADERS.iter().copied()) {
+            return self.write(OpenOptions::new().append(true).open(path)?);
+        }
+
+        let mut records = from_reader(File::open(path)?)?;
+        records.push(self.clone());
+
+        let tmp = path.with_extension("tmp");
+        to_writer(&records, File::create(&tmp)?)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Starts a builder with every value of the record, e.g. to edit it.
+    pub fn to_builder(&self) -> RecordBuilder {
+        RecordBuilder {
+            url: self.url.clone(),
+            date: self.date,
+            title: Some(self.title.clone()),
+            summary: Some(self.summary.clone()),
+            tags: self.tags.clone(),
+            origin: self.origin.clone(),
+            status: self.status,
+            rating: self.rating,
+            related: self.related.clone(),
+        }
+    }
+}
+
+/// Whether a record was actually read.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingStatus {
+    Unread,
+    Reading,
+    Read,
+    Abandoned,
+}
+
+impl ReadingStatus {
+    pub const ALL: &'static [ReadingStatus] = &[
+        ReadingStatus::Unread,
+        ReadingStatus::Reading,
+        ReadingStatus::Read,
+        ReadingStatus::Abandoned,
+    ];
+}
+
+impl fmt::Display for ReadingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadingStatus::Unread => write!(f, "unread"),
+            ReadingStatus::Reading => write!(f, "reading"),
+            ReadingStatus::Read => write!(f, "read"),
+            ReadingStatus::Abandoned => write!(f, "abandoned"),
+        }
     }
 }
 
+impl FromStr for ReadingStatus {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReadingStatus::ALL
+            .iter()
+            .find(|status| status.to_string() == s)
+            .copied()
+            .ok_or_else(|| RecordError::InvalidStatus(s.to_string()))
+    }
+}
+
+fn validate_rating(rating: u8) -> Result<u8, RecordError> {
+    if (1..=MAX_RATING).contains(&rating) {
+        Ok(rating)
+    } else {
+        Err(RecordError::InvalidRating(rating))
+    }
+}
+
+/// A link from a record to another one.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "RawLink")]
+pub struct Link {
+    url: String,
+    /// How the records relate, e.g. "response to" or "part of".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+impl Link {
+    /// A link to the given URL. Neither the URL nor the label can contain
+    /// `;` as it separates the links in the store.
+    pub fn new(url: &str, label: Option<&str>) -> Result<Link, RecordError> {
+        if url.contains(';') || url.trim().is_empty() || url.contains(char::is_whitespace) {
+            return Err(RecordError::InvalidLink(url.to_string()));
+        }
+
+        if let Some(label) = label.filter(|label| label.contains(';')) {
+            return Err(RecordError::InvalidLabel(label.to_string()));
+        }
+
+        Ok(Link {
+            url: url.to_string(),
+            label: label
+                .map(|label| label.trim().to_string())
+                .filter(|label| !label.is_empty()),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+/// A link as written in an edited record, checked by `Link::new`.
+#[derive(Deserialize)]
+struct RawLink {
+    url: String,
+    label: Option<String>,
+}
+
+impl TryFrom<RawLink> for Link {
+    type Error = RecordError;
+
+    fn try_from(raw: RawLink) -> Result<Self, Self::Error> {
+        Link::new(&raw.url, raw.label.as_deref())
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{} {}", self.url, label),
+            None => write!(f, "{}", self.url),
+        }
+    }
+}
+
+/// Reads the records from a CSV history store.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Record>, RecordError> {
+    let mut records = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for result in rdr.deserialize() {
+        let record: Record = result?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Writes the full list of records, headers included.
+pub fn to_writer<W: io::Write>(records: &[Record], writer: W) -> Result<(), RecordError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    // Written explicitly so an empty store keeps its headers.
+    wtr.write_record(HEADERS)?;
+
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct RecordBuilder {
     url: String,
-    date: String,
+    #[serde(with = "date")]
+    date: NaiveDate,
     #[serde(with = "empty_string")]
     title: Option<String>,
     #[serde(with = "empty_string")]
@@ -77,58 +317,94 @@ pub struct RecordBuilder {
     tags: Vec<String>,
     #[serde(with = "empty_string")]
     origin: Option<String>,
+    /// Empty when unknown.
+    #[serde(default, with = "empty_status")]
+    status: Option<ReadingStatus>,
+    /// From 1 to 5, 0 when not rated.
+    #[serde(default, with = "zero_rating")]
+    rating: Option<u8>,
+    /// Last, as TOML writes arrays of tables after the plain values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    related: Vec<Link>,
 }
 
 impl RecordBuilder {
     pub fn new<S: Into<String>>(url: S) -> Self {
-        let date = Utc::today();
-
         RecordBuilder {
             url: url.into(),
-            date: date.naive_utc().to_string(),
+            date: Utc::now().date_naive(),
             title: None,
             summary: None,
             tags: Vec::new(),
             origin: None,
+            status: None,
+            rating: None,
+            related: Vec::new(),
         }
     }
 
-    pub fn with_date(&mut self, date: NaiveDate) -> &Self {
-        self.date = date.to_string();
+    pub fn with_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn with_date(mut self, date: NaiveDate) -> Self {
+        self.date = date;
         self
     }
 
-    pub fn with_title<S: Into<String>>(&mut self, title: S) -> &Self {
+    pub fn with_title<S: Into<String>>(mut self, title: S) -> Self {
         self.title = Some(title.into());
         self
     }
 
-    pub fn with_summary<S: Into<String>>(&mut self, summary: S) -> &Self {
+    pub fn with_summary<S: Into<String>>(mut self, summary: S) -> Self {
         self.summary = Some(summary.into());
         self
     }
 
-    pub fn with_tags(&mut self, tags: &[&str]) -> &Self {
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
         self.tags = tags.iter().map(|s| s.to_string()).collect();
         self
     }
 
-    pub fn with_tag<S: Into<String>>(&mut self, tag: S) -> &Self {
+    pub fn with_tag<S: Into<String>>(mut self, tag: S) -> Self {
         self.tags.push(tag.into());
         self
     }
 
-    pub fn with_origin<S: Into<String>>(&mut self, origin: S) -> &Self {
+    pub fn with_origin<S: Into<String>>(mut self, origin: S) -> Self {
         self.origin = Some(origin.into());
         self
     }
 
+    pub fn with_status(mut self, status: ReadingStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn without_status(mut self) -> Self {
+        self.status = None;
+        self
+    }
+
+    /// Sets the rating, checked to be from 1 to `MAX_RATING` on `build`.
+    pub fn with_rating(mut self, rating: u8) -> Self {
+        self.rating = Some(rating);
+        self
+    }
+
+    pub fn without_rating(mut self) -> Self {
+        self.rating = None;
+        self
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
 
     pub fn date(&self) -> NaiveDate {
-        NaiveDate::parse_from_str(&self.date, "%y-%m-%d").expect("A valid date")
+        self.date
     }
 
     pub fn title(&self) -> Option<String> {
@@ -147,6 +423,14 @@ impl RecordBuilder {
         self.origin.clone()
     }
 
+    pub fn status(&self) -> Option<ReadingStatus> {
+        self.status
+    }
+
+    pub fn rating(&self) -> Option<u8> {
+        self.rating
+    }
+
     /// Builds the actual `Record`.
     ///
     /// Notice that it consumes the builder.
@@ -172,12 +456,38 @@ impl RecordBuilder {
             summary: self.summary.ok_or(RecordError::MissingSummary)?,
             tags: self.tags,
             origin: self.origin,
+            related: self.related,
+            status: self.status,
+            rating: self.rating.map(validate_rating).transpose()?,
         };
 
         Ok(record)
     }
 }
 
+/// Dates as `YYYY-MM-DD`, naming the offending value when malformed.
+pub(crate) mod date {
+    use chrono::NaiveDate;
+    use serde::Deserialize;
+
+    pub fn serialize<S>(value: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(&value.format("%F"))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+
+        NaiveDate::parse_from_str(value.trim(), "%F")
+            .map_err(|err| serde::de::Error::custom(format!("Invalid date '{}': {}", value, err)))
+    }
+}
+
 mod empty_string {
     use serde::Deserialize;
 
@@ -206,7 +516,7 @@ mod empty_string {
     }
 }
 
-mod tags {
+pub(crate) mod tags {
     use serde::Deserialize;
 
     pub fn serialize<S>(value: &[String], serializer: S) -> Result<S::Ok, S::Error>
@@ -221,7 +531,116 @@ mod tags {
         D: serde::Deserializer<'de>,
     {
         let value = String::deserialize(deserializer)?;
-        let list: Vec<String> = value.split(';').map(|s| s.to_string()).collect();
+        let list: Vec<String> = value
+            .split(';')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.to_string())
+            .collect();
+
+        Ok(list)
+    }
+}
+
+/// Ratings out of range are rejected when reading a store.
+mod rating {
+    use serde::{Deserialize, Serialize};
+
+    pub fn serialize<S>(value: &Option<u8>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Option::<u8>::deserialize(deserializer)?
+            .map(super::validate_rating)
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The status of a builder as a string, empty when unknown.
+mod empty_status {
+    use super::ReadingStatus;
+    use serde::Deserialize;
+
+    pub fn serialize<S>(value: &Option<ReadingStatus>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match value {
+            Some(status) => serializer.collect_str(status),
+            None => serializer.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<ReadingStatus>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        match value.trim() {
+            "" => Ok(None),
+            status => status.parse().map(Some).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// The rating of a builder as a number, 0 when not rated.
+mod zero_rating {
+    use serde::Deserialize;
+
+    pub fn serialize<S>(value: &Option<u8>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u8(value.unwrap_or(0))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+
+        Ok(Some(value).filter(|rating| *rating != 0))
+    }
+}
+
+/// Links are stored as `url label` pairs separated by `;`.
+mod links {
+    use super::Link;
+    use serde::Deserialize;
+
+    pub fn serialize<S>(value: &[Link], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let links: Vec<String> = value.iter().map(Link::to_string).collect();
+        serializer.serialize_str(&links.join(";"))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Link>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        let list: Vec<Link> = value
+            .split(';')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let (url, label) = match s.split_once(char::is_whitespace) {
+                    Some((url, label)) => (url, Some(label)),
+                    None => (s, None),
+                };
+                Link::new(url, label).map_err(serde::de::Error::custom)
+            })
+            .collect::<Result<_, _>>()?;
 
         Ok(list)
     }
@@ -231,6 +650,10 @@ mod tags {
 pub enum RecordError {
     MissingTitle,
     MissingSummary,
+    InvalidLink(String),
+    InvalidLabel(String),
+    InvalidStatus(String),
+    InvalidRating(u8),
     Csv(csv::Error),
     Io(io::Error),
 }
@@ -240,6 +663,18 @@ impl fmt::Display for RecordError {
         match self {
             RecordError::MissingTitle => write!(f, "'title' is a required field"),
             RecordError::MissingSummary => write!(f, "'summary' is a required field"),
+            RecordError::InvalidLink(url) => write!(f, "Invalid link to '{}'", url),
+            RecordError::InvalidLabel(label) => {
+                write!(f, "Invalid link label '{}': it can't contain ';'", label)
+            }
+            RecordError::InvalidStatus(status) => write!(
+                f,
+                "Invalid status '{}': expected one of unread, reading, read or abandoned",
+                status
+            ),
+            RecordError::InvalidRating(rating) => {
+                write!(f, "Invalid rating {}: expected 1 to {}", rating, MAX_RATING)
+            }
             RecordError::Csv(err) => write!(f, "{}", err),
             RecordError::Io(err) => write!(f, "{}", err),
         }
@@ -267,9 +702,10 @@ mod tests {
 
     #[test]
     fn serialize_builder() -> Result<(), Box<dyn Error>> {
-        let builder = RecordBuilder::new("https://www.seachess.net");
+        let builder = RecordBuilder::new("https://www.seachess.net")
+            .with_date(NaiveDate::from_ymd_opt(2020, 9, 28).unwrap());
         let actual = toml::to_string(&builder)?;
-        let expected = "url = \"https://www.seachess.net\"\ndate = \"2020-09-28\"\ntags = []\n";
+        let expected = "url = \"https://www.seachess.net\"\ndate = \"2020-09-28\"\ntitle = \"\"\nsummary = \"\"\ntags = []\norigin = \"\"\nstatus = \"\"\nrating = 0\n";
 
         assert_eq!(actual, expected);
 
@@ -278,11 +714,15 @@ mod tests {
 
     #[test]
     fn deserialize_builder() -> Result<(), Box<dyn Error>> {
-        let builder = RecordBuilder::new("https://www.seachess.net");
+        let builder = RecordBuilder::new("https://www.seachess.net")
+            .with_date(NaiveDate::from_ymd_opt(2020, 9, 28).unwrap());
         let expected = r#"
             url = "https://www.seachess.net"
             date = "2020-09-28"
+            title = ""
+            summary = ""
             tags = []
+            origin = ""
             "#;
         let actual: RecordBuilder = toml::from_str(expected)?;
 
@@ -301,10 +741,147 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn record_headers() -> Result<(), Box<dyn Error>> {
+        let record = RecordBuilder::new("https://www.seachess.net")
+            .with_title("Seachess")
+            .with_summary("A summary")
+            .build()?;
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.serialize(record)?;
+        let actual = String::from_utf8(wtr.into_inner()?)?;
+
+        assert_eq!(actual.lines().next(), Some(HEADERS.join(",").as_str()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_date() {
+        let store = "date,url,title,summary,tags,origin\n\
+                     2020-13-45,https://a.org,A,Sum,x,\n";
+        let actual = from_reader(store.as_bytes()).unwrap_err();
+
+        match actual {
+            RecordError::Csv(err) => assert_eq!(err.position().map(|p| p.line()), Some(2)),
+            err => panic!("Expected a CSV error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn legacy_store_links() -> Result<(), Box<dyn Error>> {
+        let legacy = "date,url,title,summary,tags,origin\n\
+                      2020-09-28,https://a.org,A,Sum,x,\n";
+        let mut records = from_reader(legacy.as_bytes())?;
+        assert!(records[0].related().is_empty());
+
+        records[0].link(Link::new("https://b.org", Some("response to"))?);
+        records[0].link(Link::new("https://c.org", None)?);
+        records[0].link(Link::new("https://b.org", Some("part of"))?);
+        let mut buffer = Vec::new();
+        to_writer(&records, &mut buffer)?;
+        let mut records = from_reader(&buffer[..])?;
+
+        assert_eq!(
+            records[0].related(),
+            &[
+                Link::new("https://b.org", Some("part of"))?,
+                Link::new("https://c.org", None)?
+            ]
+        );
+        assert!(records[0].unlink("https://c.org"));
+        assert!(!records[0].unlink("https://c.org"));
+        assert!(Link::new("https://d.org/a;b", None).is_err());
+        assert!(Link::new("https://b.org", Some("a;b")).is_err());
+
+        let edited: Result<RecordBuilder, _> = toml::from_str(
+            "url = \"https://a.org\"\ndate = \"2020-09-28\"\ntitle = \"A\"\n\
+             summary = \"S\"\ntags = []\norigin = \"\"\n\
+             [[related]]\nurl = \"https://b.org\"\nlabel = \"a;b\"\n",
+        );
+        assert!(edited.is_err());
+
+        let mut buffer = Vec::new();
+        to_writer(&records, &mut buffer)?;
+        assert_eq!(from_reader(&buffer[..])?[0].related(), records[0].related());
+
+        Ok(())
+    }
+
+    #[test]
+    fn status_and_rating() -> Result<(), Box<dyn Error>> {
+        let legacy = "date,url,title,summary,tags,origin\n2020-09-28,https://a.org,A,Sum,x,\n";
+        let mut records = from_reader(legacy.as_bytes())?;
+        assert_eq!(records[0].status(), None);
+        assert_eq!(records[0].rating(), None);
+
+        records[0].set_status(Some("reading".parse()?));
+        records[0].set_rating(Some(4))?;
+        assert!(records[0].set_rating(Some(6)).is_err());
+        let mut buffer = Vec::new();
+        to_writer(&records, &mut buffer)?;
+        let records = from_reader(&buffer[..])?;
+
+        assert_eq!(records[0].status(), Some(ReadingStatus::Reading));
+        assert_eq!(records[0].rating(), Some(4));
+        assert!("skimmed".parse::<ReadingStatus>().is_err());
+
+        let invalid = String::from_utf8(buffer)?.replace(",reading,4", ",reading,9");
+        assert!(from_reader(invalid.as_bytes()).is_err());
+
+        let edited: RecordBuilder = toml::from_str(
+            "url = \"https://a.org\"\ndate = \"2020-09-28\"\ntitle = \"A\"\n\
+             summary = \"S\"\ntags = []\norigin = \"\"\nstatus = \"read\"\nrating = 0\n",
+        )?;
+        assert_eq!(edited.status(), Some(ReadingStatus::Read));
+        assert_eq!(edited.rating(), None);
+        assert!(edited.with_rating(0).build().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn append_into_legacy_stores() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let legacy = [
+            "date,url,title,summary,tags,origin\n\
+             2020-09-28,https://a.org,A,Sum,x,\n",
+            "date,url,title,summary,tags,origin,related\n\
+             2020-09-28,https://a.org,A,Sum,x,,https://b.org response to\n",
+        ];
+
+        for (n, store) in legacy.iter().enumerate() {
+            let path = dir.path().join(format!("history-{}.csv", n));
+            fs::write(&path, store)?;
+            let record = RecordBuilder::new("https://c.org")
+                .with_date(NaiveDate::from_ymd_opt(2020, 10, 1).unwrap())
+                .with_title("C")
+                .with_summary("Sum")
+                .with_status(ReadingStatus::Read)
+                .with_rating(3)
+                .build()?;
+
+            record.append_into(&path)?;
+            let mut rdr = csv::Reader::from_path(&path)?;
+            assert!(rdr.headers()?.iter().eq(HEADERS.iter().copied()));
+
+            let records = from_reader(File::open(&path)?)?;
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].url(), "https://a.org");
+            assert_eq!(records[0].related().len(), n);
+            assert_eq!(records[1].url(), "https://c.org");
+            assert_eq!(records[1].status(), Some(ReadingStatus::Read));
+            assert_eq!(records[1].rating(), Some(3));
+            assert!(!path.with_extension("tmp").exists());
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn builder_chain() {
         let builder = RecordBuilder::new("https://www.seachess.net")
-            .with_date(NaiveDate::from_ymd(2020, 9, 20))
+            .with_date(NaiveDate::from_ymd_opt(2020, 9, 20).unwrap())
             .with_title("Seachess");
 
         assert_eq!(builder.title(), Some("Seachess".to_string()));
@@ -0,0 +1,489 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const FRONT_MATTER_FENCE: &str = "---";
+
+/// The columns of an idea store.
+pub const HEADERS: &[&str] = &["id", "date", "reminder", "status", "tags", "content"];
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Idea {
+    #[serde(default)]
+    id: String,
+    date: String,
+    reminder: Option<String>,
+    #[serde(default)]
+    status: Status,
+    #[serde(default, with = "crate::history::tags")]
+    tags: Vec<String>,
+    content: String,
+}
+
+impl Idea {
+    pub fn new<S: Into<String>>(content: S) -> Self {
+        let now = Utc::now();
+
+        Idea {
+            id: now.format("%Y%m%d%H%M%S").to_string(),
+            date: now.date_naive().to_string(),
+            reminder: None,
+            status: Status::default(),
+            tags: Vec::new(),
+            content: content.into(),
+        }
+    }
+
+    pub fn with_date(mut self, date: NaiveDate) -> Self {
+        self.date = date.to_string();
+        self
+    }
+
+    pub fn with_content<S: Into<String>>(mut self, content: S) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    pub fn with_reminder(mut self, reminder: Option<String>) -> Self {
+        self.reminder = reminder;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn date(&self) -> &str {
+        &self.date
+    }
+
+    pub fn reminder(&self) -> Option<&str> {
+        self.reminder.as_deref()
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The first line of the content.
+    pub fn summary(&self) -> &str {
+        self.content.lines().next().unwrap_or("")
+    }
+
+    /// Renders the idea as a markdown document with a YAML front matter.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use curator_sketch::idea::Idea;
+    ///
+    /// let idea = Idea::new("# Title\n\nSome text.");
+    /// let md = idea.to_markdown().unwrap();
+    ///
+    /// assert!(md.starts_with("---\n"));
+    /// assert!(md.ends_with("# Title\n\nSome text.\n"));
+    /// ```
+    pub fn to_markdown(&self) -> Result<String, IdeaError> {
+        let front = FrontMatter {
+            id: Some(self.id.clone()),
+            date: Some(self.date.clone()),
+            reminder: self.reminder.clone(),
+            tags: self.tags.clone(),
+            status: self.status,
+        };
+        let yaml = serde_yaml::to_string(&front)?;
+
+        Ok(format!(
+            "{fence}\n{yaml}{fence}\n\n{content}\n",
+            fence = FRONT_MATTER_FENCE,
+            yaml = yaml,
+            content = self.content.trim_end()
+        ))
+    }
+
+    /// The name of the markdown file the idea is exported to.
+    ///
+    /// Fails for identifiers that would place the file outside the export
+    /// directory.
+    pub fn file_name(&self) -> Result<String, IdeaError> {
+        check_id(&self.id)?;
+
+        Ok(format!("{}.md", self.id))
+    }
+
+    /// Parses a markdown document with an optional YAML front matter.
+    ///
+    /// Missing dates default to today and missing identifiers are left empty
+    /// so the store can assign a fresh one on merge. Dates must be written as
+    /// `YYYY-MM-DD`.
+    pub fn from_markdown(input: &str) -> Result<Idea, IdeaError> {
+        let (front, body) = split_front_matter(input)?;
+        let front: FrontMatter = match front {
+            Some(yaml) if !yaml.trim().is_empty() => serde_yaml::from_str(yaml)?,
+            _ => FrontMatter::default(),
+        };
+        let id = front.id.unwrap_or_default();
+
+        if !id.is_empty() {
+            check_id(&id)?;
+        }
+
+        let date = match front.date {
+            Some(date) => NaiveDate::parse_from_str(date.trim(), "%F")
+                .map_err(|_| IdeaError::InvalidDate(date))?,
+            None => Utc::now().date_naive(),
+        };
+
+        Ok(Idea {
+            id,
+            date: date.to_string(),
+            reminder: front.reminder.filter(|s| !s.trim().is_empty()),
+            status: front.status,
+            tags: front.tags,
+            content: body.trim().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Open,
+    Done,
+    Dropped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Open => write!(f, "open"),
+            Status::Done => write!(f, "done"),
+            Status::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct FrontMatter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reminder: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    status: Status,
+}
+
+fn split_front_matter(input: &str) -> Result<(Option<&str>, &str), IdeaError> {
+    let rest = match input
+        .strip_prefix("---\n")
+        .or_else(|| input.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return Ok((None, input)),
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FRONT_MATTER_FENCE {
+            return Ok((Some(&rest[..offset]), &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+
+    Err(IdeaError::InvalidFrontMatter(
+        "missing closing '---' fence".to_string(),
+    ))
+}
+
+/// Reads the ideas from a CSV store.
+///
+/// Ideas stored before identifiers were introduced get one derived from
+/// their date and position.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Idea>, IdeaError> {
+    let mut ideas = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for (idx, result) in rdr.deserialize().enumerate() {
+        let mut idea: Idea = result?;
+
+        if idea.id.is_empty() {
+            idea.id = format!("{}-{}", idea.date.replace('-', ""), idx);
+        }
+
+        ideas.push(idea);
+    }
+
+    Ok(ideas)
+}
+
+/// Writes the full list of ideas, headers included.
+pub fn to_writer<W: Write>(ideas: &[Idea], writer: W) -> Result<(), IdeaError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    // Written explicitly so an empty store keeps its headers.
+    wtr.write_record(HEADERS)?;
+
+    for idea in ideas {
+        wtr.serialize(idea)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+/// Merges `incoming` ideas into `store`, replacing ideas with the same
+/// identifier and appending the rest.
+///
+/// Returns the number of `(added, updated)` ideas.
+pub fn merge(store: &mut Vec<Idea>, incoming: Vec<Idea>) -> (usize, usize) {
+    let mut ids: HashSet<String> = store.iter().map(|idea| idea.id.clone()).collect();
+    let mut added = 0;
+    let mut updated = 0;
+
+    for mut idea in incoming {
+        if idea.id.is_empty() {
+            idea.id = unique_id(&Idea::new("").id, &ids);
+        }
+
+        if let Some(existing) = store.iter_mut().find(|x| x.id == idea.id) {
+            if *existing != idea {
+                *existing = idea;
+                updated += 1;
+            }
+        } else {
+            ids.insert(idea.id.clone());
+            store.push(idea);
+            added += 1;
+        }
+    }
+
+    (added, updated)
+}
+
+/// Appends a new idea, suffixing its identifier when another idea in the
+/// store already has it.
+pub fn push(store: &mut Vec<Idea>, mut idea: Idea) -> &Idea {
+    let ids: HashSet<String> = store.iter().map(|idea| idea.id.clone()).collect();
+    idea.id = unique_id(&idea.id, &ids);
+    store.push(idea);
+
+    &store[store.len() - 1]
+}
+
+/// Identifiers name the exported files, so they can't be blank nor contain
+/// path separators or `..`.
+fn check_id(id: &str) -> Result<(), IdeaError> {
+    if id.trim().is_empty() || id.contains(['/', '\\']) || id.contains("..") {
+        return Err(IdeaError::InvalidId(id.to_string()));
+    }
+
+    Ok(())
+}
+
+fn unique_id(base: &str, taken: &HashSet<String>) -> String {
+    let mut candidate = base.to_string();
+    let mut n = 1;
+
+    while taken.contains(&candidate) {
+        candidate = format!("{}-{}", base, n);
+        n += 1;
+    }
+
+    candidate
+}
+
+#[derive(Debug)]
+pub enum IdeaError {
+    InvalidFrontMatter(String),
+    InvalidId(String),
+    InvalidDate(String),
+    Yaml(serde_yaml::Error),
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for IdeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdeaError::InvalidFrontMatter(msg) => write!(f, "Invalid front matter: {}", msg),
+            IdeaError::InvalidId(id) => write!(f, "Invalid idea id '{}'", id),
+            IdeaError::InvalidDate(date) => {
+                write!(f, "Invalid idea date '{}', expected YYYY-MM-DD", date)
+            }
+            IdeaError::Yaml(err) => write!(f, "{}", err),
+            IdeaError::Csv(err) => write!(f, "{}", err),
+            IdeaError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for IdeaError {}
+
+impl From<io::Error> for IdeaError {
+    fn from(err: io::Error) -> IdeaError {
+        IdeaError::Io(err)
+    }
+}
+
+impl From<csv::Error> for IdeaError {
+    fn from(err: csv::Error) -> IdeaError {
+        IdeaError::Csv(err)
+    }
+}
+
+impl From<serde_yaml::Error> for IdeaError {
+    fn from(err: serde_yaml::Error) -> IdeaError {
+        IdeaError::Yaml(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn markdown_roundtrip() -> Result<(), Box<dyn Error>> {
+        let idea = Idea::new("# A thought\n\nWith a body.")
+            .with_reminder(Some("P1D".to_string()))
+            .with_tags(&["a", "b"])
+            .with_status(Status::Done);
+        let actual = Idea::from_markdown(&idea.to_markdown()?)?;
+
+        assert_eq!(actual, idea);
+
+        Ok(())
+    }
+
+    #[test]
+    fn markdown_without_front_matter() -> Result<(), Box<dyn Error>> {
+        let actual = Idea::from_markdown("Just a note\n")?;
+
+        assert_eq!(actual.id(), "");
+        assert_eq!(actual.content(), "Just a note");
+        assert_eq!(actual.status(), Status::Open);
+
+        Ok(())
+    }
+
+    #[test]
+    fn markdown_unclosed_front_matter() {
+        let actual = Idea::from_markdown("---\nid: x\nNo fence");
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn markdown_invalid_date() {
+        let actual = Idea::from_markdown("---\ndate: last week\n---\nA note\n");
+
+        assert_eq!(
+            actual.unwrap_err().to_string(),
+            "Invalid idea date 'last week', expected YYYY-MM-DD"
+        );
+    }
+
+    #[test]
+    fn idea_headers() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        to_writer(&[Idea::new("x")], &mut buf)?;
+        let actual = String::from_utf8(buf)?;
+
+        assert_eq!(actual.lines().next(), Some(HEADERS.join(",").as_str()));
+        assert_eq!(actual.lines().count(), 2);
+
+        let mut buf = Vec::new();
+        to_writer(&[], &mut buf)?;
+
+        assert_eq!(String::from_utf8(buf)?, format!("{}\n", HEADERS.join(",")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_store() -> Result<(), Box<dyn Error>> {
+        let csv =
+            "date,reminder,content\n2020-10-01,P1D,\"An idea\nwith lines\"\n2020-10-02,,Another\n";
+        let ideas = from_reader(csv.as_bytes())?;
+
+        assert_eq!(ideas.len(), 2);
+        assert_eq!(ideas[0].id(), "20201001-0");
+        assert_eq!(ideas[0].summary(), "An idea");
+        assert_eq!(ideas[1].reminder(), None);
+        assert!(ideas[1].tags().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_ideas() {
+        let first = Idea::new("first");
+        let mut changed = first.clone().with_status(Status::Done);
+        changed.content = "first, revisited".to_string();
+        let mut store = vec![first];
+        let (added, updated) = merge(
+            &mut store,
+            vec![changed, Idea::from_markdown("new").unwrap()],
+        );
+
+        assert_eq!((added, updated), (1, 1));
+        assert_eq!(store[0].content(), "first, revisited");
+        assert_ne!(store[1].id(), "");
+        assert_ne!(store[1].id(), store[0].id());
+    }
+
+    #[test]
+    fn reject_path_ids() {
+        assert!(Idea::from_markdown("---\nid: ../x\n---\n\nEscape").is_err());
+        assert!(Idea::from_markdown("---\nid: a/b\n---\n\nNested").is_err());
+
+        let mut idea = Idea::new("Hand edited");
+        assert_eq!(idea.file_name().unwrap(), format!("{}.md", idea.id()));
+        idea.id = "..\\x".to_string();
+        assert!(idea.file_name().is_err());
+    }
+
+    #[test]
+    fn push_same_second() {
+        let first = Idea::new("first");
+        let mut second = Idea::new("second");
+        second.id = first.id.clone();
+        let mut store = vec![first];
+        let id = push(&mut store, second).id().to_string();
+
+        assert_eq!(id, format!("{}-1", store[0].id()));
+    }
+}
@@ -0,0 +1,454 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Readers for the export files of read-later services.
+//!
+//! Every reader returns one `RecordBuilder` per bookmark, leaving validation
+//! to `RecordBuilder::build` so the caller can decide what to do with
+//! incomplete entries.
+
+use crate::history::RecordBuilder;
+use crate::source::Source;
+use chrono::prelude::*;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Pocket,
+    Pinboard,
+    Raindrop,
+    Instapaper,
+}
+
+impl Service {
+    pub fn id(&self) -> &'static str {
+        match self {
+            Service::Pocket => "pocket",
+            Service::Pinboard => "pinboard",
+            Service::Raindrop => "raindrop",
+            Service::Instapaper => "instapaper",
+        }
+    }
+
+    pub fn url(&self) -> &'static str {
+        match self {
+            Service::Pocket => "https://getpocket.com",
+            Service::Pinboard => "https://pinboard.in",
+            Service::Raindrop => "https://raindrop.io",
+            Service::Instapaper => "https://www.instapaper.com",
+        }
+    }
+
+    /// The `Source` recorded as the origin of every imported record.
+    pub fn source(&self) -> Source {
+        Source::new(
+            self.id(),
+            "service",
+            self.url(),
+            &format!("Imported from a {} export", self.id()),
+        )
+    }
+
+    /// Reads all bookmarks from the service export.
+    pub fn read<R: Read>(&self, reader: R) -> Result<Vec<RecordBuilder>, ImportError> {
+        let builders = match self {
+            Service::Pocket => pocket(reader)?,
+            Service::Pinboard => pinboard(reader)?,
+            Service::Raindrop => raindrop(reader)?,
+            Service::Instapaper => instapaper(reader)?,
+        };
+
+        Ok(builders
+            .into_iter()
+            .map(|builder| builder.with_origin(self.id()))
+            .collect())
+    }
+}
+
+impl FromStr for Service {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pocket" => Ok(Service::Pocket),
+            "pinboard" => Ok(Service::Pinboard),
+            "raindrop" => Ok(Service::Raindrop),
+            "instapaper" => Ok(Service::Instapaper),
+            _ => Err(ImportError::Invalid(format!("unknown service '{}'", s))),
+        }
+    }
+}
+
+/// Reads a Pocket HTML export.
+///
+/// Each bookmark is an anchor with `time_added` and comma-separated `tags`
+/// attributes. Pocket has no descriptions so records lack a summary.
+fn pocket<R: Read>(mut reader: R) -> Result<Vec<RecordBuilder>, ImportError> {
+    let mut html = String::new();
+    reader.read_to_string(&mut html)?;
+
+    let lower = html.to_ascii_lowercase();
+    let mut builders = Vec::new();
+    let mut offset = 0;
+
+    while let Some(pos) = lower[offset..].find("<a ") {
+        let attrs_start = offset + pos + 3;
+        let attrs_end = lower[attrs_start..]
+            .find('>')
+            .map(|end| attrs_start + end)
+            .ok_or_else(|| ImportError::Invalid("unterminated anchor".to_string()))?;
+        let text_end = lower[attrs_end..]
+            .find("</a>")
+            .map_or(lower.len(), |end| attrs_end + end);
+        let attrs = &html[attrs_start..attrs_end];
+        let title = unescape_html(html[attrs_end + 1..text_end].trim());
+        offset = text_end;
+
+        let url = match attribute(attrs, "href") {
+            Some(url) => url,
+            None => continue,
+        };
+        let mut builder = RecordBuilder::new(url.as_str());
+
+        if !title.is_empty() && title != url {
+            builder = builder.with_title(title);
+        }
+
+        if let Some(date) = attribute(attrs, "time_added").and_then(|t| from_timestamp(&t)) {
+            builder = builder.with_date(date);
+        }
+
+        for tag in attribute(attrs, "tags").unwrap_or_default().split(',') {
+            builder = with_clean_tag(builder, tag);
+        }
+
+        builders.push(builder);
+    }
+
+    Ok(builders)
+}
+
+#[derive(Debug, Deserialize)]
+struct PinboardBookmark {
+    href: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    extended: String,
+    #[serde(default)]
+    time: String,
+    #[serde(default)]
+    tags: String,
+}
+
+/// Reads a Pinboard JSON export.
+fn pinboard<R: Read>(reader: R) -> Result<Vec<RecordBuilder>, ImportError> {
+    let bookmarks: Vec<PinboardBookmark> = serde_json::from_reader(reader)?;
+
+    Ok(bookmarks
+        .into_iter()
+        .map(|bookmark| {
+            let mut builder = RecordBuilder::new(bookmark.href);
+            builder = with_text(builder, &bookmark.description, &bookmark.extended);
+
+            if let Some(date) = from_rfc3339(&bookmark.time) {
+                builder = builder.with_date(date);
+            }
+
+            for tag in bookmark.tags.split_whitespace() {
+                builder = with_clean_tag(builder, tag);
+            }
+
+            builder
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct RaindropBookmark {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    excerpt: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    created: String,
+}
+
+/// Reads a Raindrop CSV export.
+///
+/// The note takes precedence over the excerpt as the summary.
+fn raindrop<R: Read>(reader: R) -> Result<Vec<RecordBuilder>, ImportError> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let mut builders = Vec::new();
+
+    for result in rdr.deserialize() {
+        let bookmark: RaindropBookmark = result?;
+        let summary = if bookmark.note.trim().is_empty() {
+            &bookmark.excerpt
+        } else {
+            &bookmark.note
+        };
+        let mut builder = with_text(
+            RecordBuilder::new(bookmark.url.as_str()),
+            &bookmark.title,
+            summary,
+        );
+
+        if let Some(date) = from_rfc3339(&bookmark.created) {
+            builder = builder.with_date(date);
+        }
+
+        for tag in bookmark.tags.split(',') {
+            builder = with_clean_tag(builder, tag);
+        }
+
+        builders.push(builder);
+    }
+
+    Ok(builders)
+}
+
+#[derive(Debug, Deserialize)]
+struct InstapaperBookmark {
+    #[serde(rename = "URL")]
+    url: String,
+    #[serde(rename = "Title", default)]
+    title: String,
+    #[serde(rename = "Selection", default)]
+    selection: String,
+    #[serde(rename = "Folder", default)]
+    folder: String,
+    #[serde(rename = "Timestamp", default)]
+    timestamp: String,
+    #[serde(rename = "Tags", default)]
+    tags: String,
+}
+
+/// Reads an Instapaper CSV export.
+///
+/// Custom folders become tags; the built-in `Unread`, `Archive` and
+/// `Starred` folders are ignored.
+fn instapaper<R: Read>(reader: R) -> Result<Vec<RecordBuilder>, ImportError> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let mut builders = Vec::new();
+
+    for result in rdr.deserialize() {
+        let bookmark: InstapaperBookmark = result?;
+        let mut builder = with_text(
+            RecordBuilder::new(bookmark.url.as_str()),
+            &bookmark.title,
+            &bookmark.selection,
+        );
+
+        if let Some(date) = from_timestamp(&bookmark.timestamp) {
+            builder = builder.with_date(date);
+        }
+
+        match bookmark.folder.trim() {
+            "" | "Unread" | "Archive" | "Starred" => {}
+            folder => builder = with_clean_tag(builder, folder),
+        }
+
+        let tags = bookmark.tags.trim_start_matches('[').trim_end_matches(']');
+        for tag in tags.split(',') {
+            builder = with_clean_tag(builder, tag.trim().trim_matches('"'));
+        }
+
+        builders.push(builder);
+    }
+
+    Ok(builders)
+}
+
+fn with_text(builder: RecordBuilder, title: &str, summary: &str) -> RecordBuilder {
+    let builder = if title.trim().is_empty() {
+        builder
+    } else {
+        builder.with_title(title.trim())
+    };
+
+    if summary.trim().is_empty() {
+        builder
+    } else {
+        builder.with_summary(summary.trim())
+    }
+}
+
+fn with_clean_tag(builder: RecordBuilder, tag: &str) -> RecordBuilder {
+    let tag = tag.trim();
+
+    if tag.is_empty() || builder.tags().iter().any(|t| t == tag) {
+        builder
+    } else {
+        builder.with_tag(tag)
+    }
+}
+
+fn from_timestamp(value: &str) -> Option<NaiveDate> {
+    let secs = value.trim().parse::<i64>().ok()?;
+
+    DateTime::from_timestamp(secs, 0).map(|dt| dt.date_naive())
+}
+
+fn from_rfc3339(value: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|dt| dt.naive_utc().date())
+}
+
+/// Extracts the value of a double or single quoted attribute.
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut offset = 0;
+
+    while let Some(pos) = lower[offset..].find(name) {
+        let start = offset + pos;
+        let preceded = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let after = lower[start + name.len()..].trim_start();
+
+        if preceded && after.starts_with('=') {
+            let value = after[1..].trim_start();
+            let value_start = attrs.len() - value.len();
+            let quote = value.chars().next()?;
+
+            return if quote == '"' || quote == '\'' {
+                let inner = &attrs[value_start + 1..];
+                let end = inner.find(quote)?;
+                Some(unescape_html(&inner[..end]))
+            } else {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                Some(unescape_html(&attrs[value_start..value_start + end]))
+            };
+        }
+
+        offset = start + name.len();
+    }
+
+    None
+}
+
+fn unescape_html(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Invalid(String),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Invalid(msg) => write!(f, "{}", msg),
+            ImportError::Json(err) => write!(f, "{}", err),
+            ImportError::Csv(err) => write!(f, "{}", err),
+            ImportError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> ImportError {
+        ImportError::Io(err)
+    }
+}
+
+impl From<csv::Error> for ImportError {
+    fn from(err: csv::Error) -> ImportError {
+        ImportError::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> ImportError {
+        ImportError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn read_pocket() -> Result<(), Box<dyn Error>> {
+        let html = r#"<!DOCTYPE html><html><body>
+            <h1>Unread</h1>
+            <ul>
+            <li><a href="https://example.org/a" time_added="1601280000" tags="rust,cli">A &amp; B</a></li>
+            <li><a href="https://example.org/b" time_added="1601280000" tags="">https://example.org/b</a></li>
+            </ul></body></html>"#;
+        let builders = Service::Pocket.read(html.as_bytes())?;
+
+        assert_eq!(builders.len(), 2);
+        assert_eq!(builders[0].url(), "https://example.org/a");
+        assert_eq!(builders[0].title(), Some("A & B".to_string()));
+        assert_eq!(builders[0].tags(), vec!["rust", "cli"]);
+        assert_eq!(builders[0].origin(), Some("pocket".to_string()));
+        assert_eq!(builders[1].title(), None);
+        assert!(
+            builders[0].clone().build().is_err(),
+            "Pocket has no summaries"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_pinboard() -> Result<(), Box<dyn Error>> {
+        let json = r#"[{"href":"https://example.org","description":"Example","extended":"A site","time":"2020-09-28T10:00:00Z","tags":"a b"}]"#;
+        let builders = Service::Pinboard.read(json.as_bytes())?;
+        let record = builders[0].clone().build()?;
+
+        assert_eq!(record.title(), "Example");
+        assert_eq!(record.summary(), "A site");
+        assert_eq!(record.tags(), vec!["a", "b"]);
+        assert_eq!(record.date(), NaiveDate::from_ymd_opt(2020, 9, 28).unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_raindrop() -> Result<(), Box<dyn Error>> {
+        let csv = "id,title,note,excerpt,url,folder,tags,created\n1,Example,,An excerpt,https://example.org,Unsorted,\"a, b\",2020-09-28T10:00:00.000Z\n";
+        let builders = Service::Raindrop.read(csv.as_bytes())?;
+
+        assert_eq!(builders[0].summary(), Some("An excerpt".to_string()));
+        assert_eq!(builders[0].tags(), vec!["a", "b"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_instapaper() -> Result<(), Box<dyn Error>> {
+        let csv = "URL,Title,Selection,Folder,Timestamp\nhttps://example.org,Example,,Reading,1601280000\n";
+        let builders = Service::Instapaper.read(csv.as_bytes())?;
+
+        assert_eq!(builders[0].tags(), vec!["Reading"]);
+        assert_eq!(builders[0].summary(), None);
+
+        Ok(())
+    }
+}
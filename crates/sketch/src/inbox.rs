@@ -0,0 +1,313 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Candidate links waiting to be read and summarised.
+
+use crate::history::RecordBuilder;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The columns of an inbox store.
+pub const HEADERS: &[&str] = &["id", "date", "url", "title", "origin", "state"];
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Item {
+    /// Identifies the item within its origin, e.g. the feed entry id.
+    id: String,
+    date: String,
+    url: String,
+    title: Option<String>,
+    origin: Option<String>,
+    #[serde(default)]
+    state: State,
+}
+
+impl Item {
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        let url = url.into();
+
+        Item {
+            id: url.clone(),
+            date: Utc::now().date_naive().to_string(),
+            url,
+            title: None,
+            origin: None,
+            state: State::default(),
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = id.into();
+        self
+    }
+
+    pub fn with_title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn date(&self) -> &str {
+        &self.date
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Whether the item still needs a decision.
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Pending | State::Deferred)
+    }
+
+    /// Whether the item comes from the same entry of the same origin.
+    pub fn same_entry(&self, other: &Item) -> bool {
+        self.origin == other.origin && self.id == other.id
+    }
+
+    /// Starts a history record with everything known about the item.
+    pub fn to_builder(&self) -> RecordBuilder {
+        let mut builder = RecordBuilder::new(self.url.as_str());
+
+        if let Some(title) = &self.title {
+            builder = builder.with_title(title.as_str());
+        }
+
+        if let Some(origin) = &self.origin {
+            builder = builder.with_origin(origin.as_str());
+        }
+
+        builder
+    }
+}
+
+/// Adds the item to the inbox, reopening the entry if it was already triaged
+/// so each entry keeps a single row. Returns false when the entry is still
+/// open.
+pub fn capture(items: &mut Vec<Item>, item: Item) -> bool {
+    match items.iter_mut().find(|x| x.same_entry(&item)) {
+        Some(existing) if existing.is_open() => false,
+        Some(existing) => {
+            let title = item.title.or_else(|| existing.title.take());
+            *existing = Item { title, ..item };
+            true
+        }
+        None => {
+            items.push(item);
+            true
+        }
+    }
+}
+
+/// The triage state of an inbox item.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// Not looked at yet.
+    #[default]
+    Pending,
+    /// Looked at and left for a later triage.
+    Deferred,
+    /// Summarised into the history store.
+    Kept,
+    /// Not worth keeping.
+    Discarded,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Pending => write!(f, "pending"),
+            State::Deferred => write!(f, "deferred"),
+            State::Kept => write!(f, "kept"),
+            State::Discarded => write!(f, "discarded"),
+        }
+    }
+}
+
+/// Reads the items from a CSV inbox store.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Item>, InboxError> {
+    let mut items = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for result in rdr.deserialize() {
+        let item: Item = result?;
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+/// Writes the full list of items, headers included.
+pub fn to_writer<W: Write>(items: &[Item], writer: W) -> Result<(), InboxError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    for item in items {
+        wtr.serialize(item)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+/// Reads the entries of an RSS, Atom or JSON feed as inbox items for the
+/// given origin.
+pub fn from_feed<R: Read>(origin: &str, reader: R) -> Result<Vec<Item>, InboxError> {
+    let feed = feed_rs::parser::parse(reader).map_err(|err| InboxError::Feed(err.to_string()))?;
+    let mut items = Vec::new();
+
+    for entry in feed.entries {
+        let url = match entry.links.first() {
+            Some(link) => link.href.clone(),
+            None => continue,
+        };
+        let mut item = Item::new(url).with_id(entry.id).with_origin(origin);
+
+        if let Some(title) = entry.title {
+            item = item.with_title(title.content.trim());
+        }
+
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+#[derive(Debug)]
+pub enum InboxError {
+    Feed(String),
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for InboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InboxError::Feed(msg) => write!(f, "Invalid feed: {}", msg),
+            InboxError::Csv(err) => write!(f, "{}", err),
+            InboxError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for InboxError {}
+
+impl From<io::Error> for InboxError {
+    fn from(err: io::Error) -> InboxError {
+        InboxError::Io(err)
+    }
+}
+
+impl From<csv::Error> for InboxError {
+    fn from(err: csv::Error) -> InboxError {
+        InboxError::Csv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn rss_entries() -> Result<(), Box<dyn Error>> {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel><title>Seachess</title><link>https://www.seachess.net</link>
+            <item><title>First</title><link>https://www.seachess.net/first</link><guid>first</guid></item>
+            <item><title>No link</title><guid>nolink</guid></item>
+            </channel></rss>"#;
+        let items = from_feed("seachess", rss.as_bytes())?;
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id(), "first");
+        assert_eq!(items[0].title(), Some("First"));
+        assert_eq!(items[0].origin(), Some("seachess"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_feed_entries() -> Result<(), Box<dyn Error>> {
+        let json = r#"{"version":"https://jsonfeed.org/version/1.1","title":"X",
+            "items":[{"id":"1","url":"https://example.org/1","title":"One"}]}"#;
+        let items = from_feed("x", json.as_bytes())?;
+
+        assert_eq!(items[0].url(), "https://example.org/1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn stateless_rows() -> Result<(), Box<dyn Error>> {
+        let csv = "id,date,url,title,origin\n1,2020-10-01,https://a.org/1,One,a\n";
+        let items = from_reader(csv.as_bytes())?;
+
+        assert_eq!(items[0].state(), State::Pending);
+        assert!(items[0].is_open());
+
+        Ok(())
+    }
+
+    #[test]
+    fn capture_triaged_entry() {
+        let mut items = Vec::new();
+        assert!(capture(
+            &mut items,
+            Item::new("https://a.org").with_title("A")
+        ));
+        assert!(!capture(&mut items, Item::new("https://a.org")));
+        items[0].set_state(State::Discarded);
+
+        assert!(capture(&mut items, Item::new("https://a.org")));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].state(), State::Pending);
+        assert_eq!(items[0].title(), Some("A"));
+
+        let triaged = items[0].clone();
+        if let Some(x) = items.iter_mut().find(|x| x.same_entry(&triaged)) {
+            x.set_state(State::Kept);
+        }
+        assert_eq!(items[0].state(), State::Kept);
+    }
+
+    #[test]
+    fn builder_from_item() {
+        let builder = Item::new("https://example.org")
+            .with_title("Example")
+            .with_origin("x")
+            .to_builder();
+
+        assert_eq!(builder.title(), Some("Example".to_string()));
+        assert_eq!(builder.origin(), Some("x".to_string()));
+    }
+}
@@ -4,5 +4,13 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
+pub mod annotation;
+pub mod archive;
 pub mod history;
+pub mod idea;
+pub mod import;
+pub mod inbox;
+pub mod related;
 pub mod source;
+pub mod suggest;
+pub mod text;
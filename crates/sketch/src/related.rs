@@ -0,0 +1,202 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Finds the history records on the same topic as another one.
+//!
+//! Every record is a TF-IDF vector of the words in its URL, title, summary
+//! and tags, with tags counting twice, and records are compared by the
+//! cosine of their vectors.
+
+use crate::history::Record;
+use std::collections::HashMap;
+
+/// Words too common to tell topics apart, URL noise included.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "by", "com", "for", "from", "how", "htm",
+    "html", "http", "https", "in", "index", "into", "is", "it", "its", "net", "of", "on", "or",
+    "org", "that", "the", "this", "to", "was", "what", "when", "why", "with", "www", "you", "your",
+];
+
+/// Records scoring lower are not considered related.
+const MIN_SCORE: f32 = 0.05;
+
+type Vector = HashMap<String, f32>;
+
+#[derive(Debug)]
+pub struct Related<'a> {
+    records: &'a [Record],
+    vectors: Vec<Vector>,
+    idf: HashMap<String, f32>,
+}
+
+impl<'a> Related<'a> {
+    pub fn new(records: &'a [Record]) -> Self {
+        let terms: Vec<Vec<String>> = records
+            .iter()
+            .map(|record| {
+                terms(
+                    record.url(),
+                    record.title(),
+                    record.summary(),
+                    &record.tags(),
+                )
+            })
+            .collect();
+        let mut frequency: HashMap<&str, usize> = HashMap::new();
+
+        for doc in &terms {
+            let mut seen: Vec<&str> = doc.iter().map(String::as_str).collect();
+            seen.sort_unstable();
+            seen.dedup();
+            for term in seen {
+                *frequency.entry(term).or_insert(0) += 1;
+            }
+        }
+
+        // Smoothed, so terms found in every record still count a little.
+        let total = records.len() as f32;
+        let idf: HashMap<String, f32> = frequency
+            .into_iter()
+            .map(|(term, n)| {
+                let weight = ((1.0 + total) / (1.0 + n as f32)).ln() + 1.0;
+                (term.to_string(), weight)
+            })
+            .collect();
+        let vectors = terms.iter().map(|doc| vector(doc, &idf)).collect();
+
+        Related {
+            records,
+            vectors,
+            idf,
+        }
+    }
+
+    /// The records most similar to the given content, best first, leaving
+    /// out the record with the same URL.
+    pub fn to(
+        &self,
+        url: &str,
+        title: &str,
+        summary: &str,
+        tags: &[String],
+        limit: usize,
+    ) -> Vec<(&'a Record, f32)> {
+        let query = vector(&terms(url, title, summary, tags), &self.idf);
+        let mut scored: Vec<(&'a Record, f32)> = self
+            .records
+            .iter()
+            .zip(&self.vectors)
+            .filter(|(record, _)| record.url() != url)
+            .map(|(record, vector)| (record, cosine(&query, vector)))
+            .filter(|(_, score)| *score >= MIN_SCORE)
+            .collect();
+
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(limit);
+
+        scored
+    }
+
+    /// The records most similar to the given one.
+    pub fn to_record(&self, record: &Record, limit: usize) -> Vec<(&'a Record, f32)> {
+        self.to(
+            record.url(),
+            record.title(),
+            record.summary(),
+            &record.tags(),
+            limit,
+        )
+    }
+}
+
+/// The words of the content, lowercased and without stopwords.
+fn terms(url: &str, title: &str, summary: &str, tags: &[String]) -> Vec<String> {
+    let mut terms = words(url);
+    terms.extend(words(title));
+    terms.extend(words(summary));
+
+    for tag in tags {
+        let tag = tag.to_lowercase();
+        terms.push(tag.clone());
+        terms.push(tag);
+    }
+
+    terms
+}
+
+/// The words of the text, lowercased and without stopwords.
+pub(crate) fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// The TF-IDF vector of the terms, normalised to unit length.
+fn vector(terms: &[String], idf: &HashMap<String, f32>) -> Vector {
+    let mut vector = Vector::new();
+
+    for term in terms {
+        if let Some(weight) = idf.get(term) {
+            *vector.entry(term.clone()).or_insert(0.0) += weight;
+        }
+    }
+
+    let norm = vector.values().map(|w| w * w).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for weight in vector.values_mut() {
+            *weight /= norm;
+        }
+    }
+
+    vector
+}
+
+fn cosine(a: &Vector, b: &Vector) -> f32 {
+    let (small, large) = if a.len() < b.len() { (a, b) } else { (b, a) };
+
+    small
+        .iter()
+        .filter_map(|(term, weight)| large.get(term).map(|other| weight * other))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similar_records() -> Result<(), Box<dyn std::error::Error>> {
+        let records = vec![
+            Record::new("https://a.org/rust-lifetimes")
+                .with_title("Rust lifetimes explained")
+                .with_summary("How the borrow checker reasons about lifetimes.")
+                .with_tags(&["rust"])
+                .build()?,
+            Record::new("https://b.org/borrowing")
+                .with_title("Borrowing in Rust")
+                .with_summary("The borrow checker and references.")
+                .with_tags(&["rust"])
+                .build()?,
+            Record::new("https://c.org/sourdough")
+                .with_title("Sourdough bread")
+                .with_summary("Baking with a starter.")
+                .with_tags(&["baking"])
+                .build()?,
+        ];
+        let related = Related::new(&records);
+        let found = related.to_record(&records[0], 5);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0.url(), "https://b.org/borrowing");
+
+        let draft = related.to("https://d.org/bread", "", "", &["baking".to_string()], 5);
+        assert_eq!(draft[0].0.url(), "https://c.org/sourdough");
+
+        Ok(())
+    }
+}
@@ -4,10 +4,19 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
+use crate::suggest;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
-use std::io::{self, Read};
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// The columns of a source store.
+pub const HEADERS: &[&str] = &["id", "type", "url", "comment"];
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Source {
@@ -19,6 +28,15 @@ pub struct Source {
 }
 
 impl Source {
+    pub fn new(id: &str, type_: &str, url: &str, comment: &str) -> Self {
+        Source {
+            id: id.to_string(),
+            type_: type_.to_string(),
+            url: url.to_string(),
+            comment: comment.to_string(),
+        }
+    }
+
     pub fn id(&self) -> &str {
         &self.id
     }
@@ -30,6 +48,24 @@ impl Source {
     pub fn url(&self) -> &str {
         &self.url
     }
+
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<(), SourceError> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        wtr.serialize(self)?;
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    pub fn append_into<P: AsRef<Path>>(&self, path: P) -> Result<(), SourceError> {
+        self.write(OpenOptions::new().append(true).open(path)?)
+    }
 }
 
 pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Source>, SourceError> {
@@ -44,13 +80,246 @@ pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Source>, SourceError> {
     Ok(sources)
 }
 
-/// Represents a CLI error.
-#[derive(PartialEq, Debug, Clone)]
-pub struct SourceError(String);
+/// Writes the full list of sources, headers included.
+pub fn to_writer<W: Write>(sources: &[Source], writer: W) -> Result<(), SourceError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    // Written explicitly so an empty store keeps its headers.
+    wtr.write_record(HEADERS)?;
+
+    for source in sources {
+        wtr.serialize(source)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+/// Reads the feed subscriptions from an OPML document.
+///
+/// Every outline with an `xmlUrl` becomes a `feed` source. Its identifier is
+/// derived from the outline title, or the feed host when the title has no
+/// letters or digits, and the enclosing folders (or the `category`
+/// attribute) are kept as the comment. Feeds listed more than once are kept
+/// once.
+///
+/// ## Examples
+///
+/// ```
+/// use curator_sketch::source;
+///
+/// let opml = r#"<opml version="2.0"><body>
+///   <outline text="Tech">
+///     <outline text="Seachess" type="rss" xmlUrl="https://www.seachess.net/feed.xml"/>
+///   </outline>
+/// </body></opml>"#;
+/// let sources = source::from_opml(opml.as_bytes()).unwrap();
+///
+/// assert_eq!(sources[0].id(), "seachess");
+/// assert_eq!(sources[0].type_(), "feed");
+/// assert_eq!(sources[0].comment(), "Tech");
+/// ```
+pub fn from_opml<R: Read>(mut reader: R) -> Result<Vec<Source>, SourceError> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let mut rdr = quick_xml::Reader::from_str(&content);
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut sources: Vec<Source> = Vec::new();
+
+    loop {
+        match rdr.read_event().map_err(xml_error)? {
+            Event::Start(e) if e.name().as_ref() == b"outline" => {
+                let outline = Outline::from_element(&e)?;
+                let folder = if outline.xml_url.is_none() {
+                    outline.title.clone()
+                } else {
+                    None
+                };
+
+                if let Some(source) = outline.into_source(&folders) {
+                    push_new(&mut sources, source);
+                }
+                folders.push(folder);
+            }
+            Event::Empty(e) if e.name().as_ref() == b"outline" => {
+                if let Some(source) = Outline::from_element(&e)?.into_source(&folders) {
+                    push_new(&mut sources, source);
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"outline" => {
+                folders.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Keeps the first of the feeds listed more than once.
+fn push_new(sources: &mut Vec<Source>, source: Source) {
+    if !sources.iter().any(|x| x.url == source.url) {
+        sources.push(source);
+    }
+}
+
+/// Writes the given sources as an OPML document.
+pub fn to_opml<W: Write>(
+    sources: &[Source],
+    title: &str,
+    mut writer: W,
+) -> Result<(), SourceError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<opml version="2.0">"#)?;
+    writeln!(writer, "  <head>")?;
+    writeln!(writer, "    <title>{}</title>", escape(title))?;
+    writeln!(writer, "  </head>")?;
+    writeln!(writer, "  <body>")?;
+
+    for source in sources {
+        write!(
+            writer,
+            r#"    <outline type="rss" text="{id}" title="{id}" xmlUrl="{url}""#,
+            id = escape(source.id()),
+            url = escape(source.url())
+        )?;
+        if !source.comment().is_empty() {
+            write!(writer, r#" category="{}""#, escape(source.comment()))?;
+        }
+        writeln!(writer, "/>")?;
+    }
+
+    writeln!(writer, "  </body>")?;
+    writeln!(writer, "</opml>")?;
+
+    Ok(())
+}
+
+/// Gives every source an identifier not taken by `existing` or by the
+/// sources before it.
+pub fn dedup_ids(sources: &mut [Source], existing: &[Source]) {
+    let mut taken: HashSet<String> = existing.iter().map(|s| s.id.clone()).collect();
+
+    for source in sources.iter_mut() {
+        let base = source.id.clone();
+        let mut n = 1;
+
+        while taken.contains(&source.id) {
+            n += 1;
+            source.id = format!("{}-{}", base, n);
+        }
+
+        taken.insert(source.id.clone());
+    }
+}
+
+struct Outline {
+    title: Option<String>,
+    xml_url: Option<String>,
+    category: Option<String>,
+}
+
+impl Outline {
+    fn from_element(e: &BytesStart) -> Result<Outline, SourceError> {
+        let mut outline = Outline {
+            title: None,
+            xml_url: None,
+            category: None,
+        };
+        let mut text = None;
+
+        for attr in e.attributes() {
+            let attr = attr.map_err(xml_error)?;
+            let value = attr.unescape_value().map_err(xml_error)?.trim().to_string();
+
+            if value.is_empty() {
+                continue;
+            }
+
+            match attr.key.as_ref() {
+                b"title" => outline.title = Some(value),
+                b"text" => text = Some(value),
+                b"xmlUrl" => outline.xml_url = Some(value),
+                b"category" => outline.category = Some(value),
+                _ => {}
+            }
+        }
+
+        if outline.title.is_none() {
+            outline.title = text;
+        }
+
+        Ok(outline)
+    }
+
+    fn into_source(self, folders: &[Option<String>]) -> Option<Source> {
+        let url = self.xml_url?;
+        let title = self.title.unwrap_or_else(|| url.clone());
+        let comment = self.category.unwrap_or_else(|| {
+            folders
+                .iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("/")
+        });
+
+        let id = Some(slugify(&title))
+            .filter(|id| !id.is_empty())
+            .or_else(|| suggest::domain(&url).map(slugify))
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| "feed".to_string());
+
+        Some(Source::new(&id, "feed", &url, &comment))
+    }
+}
+
+/// Identifiers name the source in URLs and commands, so they are limited to
+/// letters, digits, `-` and `_`, as produced by `slugify`.
+pub fn check_id(id: &str) -> Result<(), SourceError> {
+    let valid = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+
+    if id.is_empty() || !id.chars().all(valid) {
+        return Err(SourceError::Invalid(format!(
+            "Invalid source id '{}', use letters, digits, '-' or '_'",
+            id
+        )));
+    }
+
+    Ok(())
+}
+
+fn slugify(value: &str) -> String {
+    let mut slug = String::new();
+
+    for c in value.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_matches('-').to_string()
+}
+
+fn xml_error<E: fmt::Display>(err: E) -> SourceError {
+    SourceError::Invalid(format!("Invalid OPML: {}", err))
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    Invalid(String),
+    Csv(csv::Error),
+    Io(io::Error),
+}
 
 impl SourceError {
     pub fn new(msg: &str) -> Self {
-        Self(msg.to_string())
+        SourceError::Invalid(msg.to_string())
     }
 }
 
@@ -58,30 +327,108 @@ impl Error for SourceError {}
 
 impl From<io::Error> for SourceError {
     fn from(err: io::Error) -> SourceError {
-        SourceError(format!("{}", err))
+        SourceError::Io(err)
     }
 }
 
 impl From<csv::Error> for SourceError {
     fn from(err: csv::Error) -> SourceError {
-        SourceError(format!("{}", err))
+        SourceError::Csv(err)
     }
 }
 
 impl From<toml::de::Error> for SourceError {
     fn from(err: toml::de::Error) -> SourceError {
-        SourceError(format!("{}", err))
+        SourceError::Invalid(format!("{}", err))
     }
 }
 
 impl From<toml::ser::Error> for SourceError {
     fn from(err: toml::ser::Error) -> SourceError {
-        SourceError(format!("{}", err))
+        SourceError::Invalid(format!("{}", err))
     }
 }
 
 impl fmt::Display for SourceError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            SourceError::Invalid(msg) => write!(f, "{}", msg),
+            SourceError::Csv(err) => write!(f, "{}", err),
+            SourceError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn opml_roundtrip() -> Result<(), Box<dyn Error>> {
+        let sources = vec![
+            Source::new(
+                "seachess",
+                "feed",
+                "https://www.seachess.net/feed.xml",
+                "Tech",
+            ),
+            Source::new("a-b", "feed", "https://example.org/?a=1&b=2", ""),
+        ];
+        let mut buf = Vec::new();
+        to_opml(&sources, "Curator", &mut buf)?;
+        let actual = from_opml(&buf[..])?;
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].comment(), "Tech");
+        assert_eq!(actual[1].url(), "https://example.org/?a=1&b=2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn opml_nested_folders() -> Result<(), Box<dyn Error>> {
+        let opml = r#"<opml version="1.0"><body>
+            <outline title="News"><outline text="Dev">
+                <outline text="Rust Blog" xmlUrl="https://blog.rust-lang.org/feed.xml"/>
+            </outline></outline>
+            <outline text="Loose" xmlUrl="https://example.org/rss"/>
+        </body></opml>"#;
+        let actual = from_opml(opml.as_bytes())?;
+
+        assert_eq!(actual[0].id(), "rust-blog");
+        assert_eq!(actual[0].comment(), "News/Dev");
+        assert_eq!(actual[1].comment(), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn opml_duplicates_and_blank_titles() -> Result<(), Box<dyn Error>> {
+        let opml = r#"<opml version="1.0"><body>
+            <outline text="Rust" xmlUrl="https://blog.rust-lang.org/feed.xml"/>
+            <outline text="Rust again" xmlUrl="https://blog.rust-lang.org/feed.xml"/>
+            <outline text="***" xmlUrl="https://www.example.org/rss"/>
+        </body></opml>"#;
+        let actual = from_opml(opml.as_bytes())?;
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].id(), "rust");
+        assert_eq!(actual[1].id(), "example-org");
+
+        Ok(())
+    }
+
+    #[test]
+    fn unique_ids() {
+        let existing = vec![Source::new("a", "feed", "x", "")];
+        let mut sources = vec![
+            Source::new("a", "feed", "y", ""),
+            Source::new("a", "feed", "z", ""),
+        ];
+        dedup_ids(&mut sources, &existing);
+
+        assert_eq!(sources[0].id(), "a-2");
+        assert_eq!(sources[1].id(), "a-3");
     }
 }
@@ -0,0 +1,266 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Suggests tags for new records, learned from the history.
+//!
+//! Every tag is a naive Bayes classifier telling records with the tag from
+//! records without it, using the words in the title and summary and the
+//! domain of the URL.
+
+use crate::history::Record;
+use crate::related;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+
+/// Tags less likely than this are not suggested, nor are tags the content
+/// makes no likelier than they are across the history.
+const MIN_PROBABILITY: f64 = 0.2;
+
+/// Tags on fewer records are too rare to learn from.
+const MIN_RECORDS: usize = 2;
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Model {
+    /// The number of records trained on.
+    records: usize,
+    /// A hash of the content trained on, to tell when the model is stale.
+    #[serde(default)]
+    fingerprint: u64,
+    /// The number of records having each tag.
+    tags: BTreeMap<String, usize>,
+    /// The number of records having each feature.
+    features: BTreeMap<String, usize>,
+    /// The number of records having each feature, by tag.
+    tagged: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Model {
+    /// Learns the tags of the given records.
+    pub fn train(records: &[Record]) -> Self {
+        let mut model = Model {
+            records: records.len(),
+            fingerprint: fingerprint(records),
+            ..Model::default()
+        };
+
+        for record in records {
+            let features = features(record.url(), record.title(), record.summary());
+
+            for feature in &features {
+                *model.features.entry(feature.clone()).or_insert(0) += 1;
+            }
+
+            for tag in record.tags() {
+                *model.tags.entry(tag.clone()).or_insert(0) += 1;
+                let counts = model.tagged.entry(tag).or_default();
+                for feature in &features {
+                    *counts.entry(feature.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+
+        model
+    }
+
+    /// The number of records the model was trained on.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Whether the model was trained on exactly these records, so tags edited
+    /// or records replaced since make it stale even if the count is the same.
+    pub fn is_trained_on(&self, records: &[Record]) -> bool {
+        self.records == records.len() && self.fingerprint == fingerprint(records)
+    }
+
+    /// The number of tags the model can suggest.
+    pub fn tags(&self) -> usize {
+        self.tags.values().filter(|n| **n >= MIN_RECORDS).count()
+    }
+
+    /// The most likely tags for the content with their probability, up to
+    /// `limit`, the most likely first.
+    pub fn suggest(
+        &self,
+        url: &str,
+        title: &str,
+        summary: &str,
+        limit: usize,
+    ) -> Vec<(String, f64)> {
+        let features = features(url, title, summary);
+        let mut suggested: Vec<(String, f64)> = self
+            .tags
+            .iter()
+            .filter(|(_, n)| **n >= MIN_RECORDS)
+            .map(|(tag, n)| {
+                let prior = *n as f64 / self.records as f64;
+                (tag.clone(), self.probability(tag, *n, &features), prior)
+            })
+            .filter(|(_, p, prior)| *p >= MIN_PROBABILITY && p > prior)
+            .map(|(tag, p, _)| (tag, p))
+            .collect();
+
+        suggested.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        suggested.truncate(limit);
+
+        suggested
+    }
+
+    /// The probability of the tag given the features, comparing the records
+    /// with the tag to the rest with Laplace smoothing.
+    fn probability(&self, tag: &str, with: usize, features: &BTreeSet<String>) -> f64 {
+        let without = self.records.saturating_sub(with);
+        let counts = &self.tagged[tag];
+        let mut log_odds = ((with as f64 + 1.0) / (without as f64 + 1.0)).ln();
+
+        for feature in features {
+            let total = match self.features.get(feature) {
+                Some(total) => *total,
+                None => continue,
+            };
+            let tagged = counts.get(feature).copied().unwrap_or(0);
+            let given_tag = (tagged as f64 + 1.0) / (with as f64 + 2.0);
+            let given_other = (total.saturating_sub(tagged) as f64 + 1.0) / (without as f64 + 2.0);
+
+            log_odds += (given_tag / given_other).ln();
+        }
+
+        1.0 / (1.0 + (-log_odds).exp())
+    }
+}
+
+/// Hashes what the model learns from. The hash may change with the Rust
+/// version, which only costs a retrain.
+fn fingerprint(records: &[Record]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for record in records {
+        (
+            record.url(),
+            record.title(),
+            record.summary(),
+            record.tags(),
+        )
+            .hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+/// The words of the title and summary plus the domain of the URL.
+fn features(url: &str, title: &str, summary: &str) -> BTreeSet<String> {
+    let mut features: BTreeSet<String> = related::words(title)
+        .into_iter()
+        .chain(related::words(summary))
+        .collect();
+
+    if let Some(domain) = domain(url) {
+        features.insert(format!("domain:{}", domain));
+    }
+
+    features
+}
+
+/// The host of a URL without any `www.` prefix.
+pub fn domain(url: &str) -> Option<&str> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next()?.trim_start_matches("www.");
+
+    Some(host).filter(|host| !host.is_empty())
+}
+
+/// Reads a model saved with `to_writer`.
+pub fn from_reader<R: Read>(reader: R) -> Result<Model, SuggestError> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn to_writer<W: Write>(model: &Model, writer: W) -> Result<(), SuggestError> {
+    Ok(serde_json::to_writer(writer, model)?)
+}
+
+#[derive(Debug)]
+pub enum SuggestError {
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for SuggestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SuggestError::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for SuggestError {}
+
+impl From<serde_json::Error> for SuggestError {
+    fn from(err: serde_json::Error) -> SuggestError {
+        SuggestError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggest_tags() -> Result<(), Box<dyn Error>> {
+        let records = [
+            (
+                "https://blog.rust-lang.org/a",
+                "Async Rust",
+                &["rust", "async"][..],
+            ),
+            ("https://blog.rust-lang.org/b", "Rust traits", &["rust"]),
+            ("https://doc.rust-lang.org/c", "Rust macros", &["rust"]),
+            ("https://bread.org/a", "Sourdough starter", &["baking"]),
+            ("https://www.bread.org/b", "Rye sourdough", &["baking"]),
+        ]
+        .iter()
+        .map(|(url, title, tags)| {
+            Record::new(*url)
+                .with_title(*title)
+                .with_summary("A summary.")
+                .with_tags(tags)
+                .build()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+        let model = Model::train(&records);
+        let mut buffer = Vec::new();
+        to_writer(&model, &mut buffer)?;
+        let model = from_reader(&buffer[..])?;
+
+        let rust = model.suggest("https://blog.rust-lang.org/d", "Rust lifetimes", "", 3);
+        assert_eq!(rust[0].0, "rust");
+        assert!(rust
+            .iter()
+            .all(|(tag, _)| tag != "baking" && tag != "async"));
+
+        let baking = model.suggest("https://bread.org/c", "", "", 3);
+        assert_eq!(baking[0].0, "baking");
+
+        assert_eq!(model.records(), 5);
+        assert_eq!(model.tags(), 2);
+        assert!(model.is_trained_on(&records));
+
+        let mut retagged = records.clone();
+        retagged[1] = records[1].to_builder().with_tags(&["traits"]).build()?;
+        assert!(!model.is_trained_on(&retagged));
+
+        Ok(())
+    }
+}
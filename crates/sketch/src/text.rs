@@ -0,0 +1,152 @@
+// Copyright 2020 Arnau Siches
+
+// Licensed under the MIT license <LICENCE or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except
+// according to those terms.
+
+//! The readable text of history records, stripped of the page boilerplate.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The columns of a text store.
+pub const HEADERS: &[&str] = &["url", "words", "minutes", "text"];
+
+/// The words read per minute when estimating the reading time.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Text {
+    /// The URL of the record the text belongs to.
+    url: String,
+    words: usize,
+    /// The estimated reading time, rounded up.
+    minutes: usize,
+    text: String,
+}
+
+impl Text {
+    pub fn new(url: &str, text: &str) -> Self {
+        let words = text.split_whitespace().count();
+
+        Text {
+            url: url.to_string(),
+            words,
+            minutes: words.div_ceil(WORDS_PER_MINUTE),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    pub fn minutes(&self) -> usize {
+        self.minutes
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// The text of the given URL.
+pub fn find<'a>(texts: &'a [Text], url: &str) -> Option<&'a Text> {
+    texts.iter().find(|text| text.url == url)
+}
+
+/// Replaces the text for the same URL or adds it.
+pub fn upsert(texts: &mut Vec<Text>, text: Text) {
+    match texts.iter_mut().find(|t| t.url == text.url) {
+        Some(existing) => *existing = text,
+        None => texts.push(text),
+    }
+}
+
+/// Reads the texts from a CSV text store.
+pub fn from_reader<R: Read>(reader: R) -> Result<Vec<Text>, TextError> {
+    let mut texts = Vec::new();
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    for result in rdr.deserialize() {
+        let text: Text = result?;
+        texts.push(text);
+    }
+
+    Ok(texts)
+}
+
+/// Writes the full list of texts, headers included.
+pub fn to_writer<W: Write>(texts: &[Text], writer: W) -> Result<(), TextError> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    wtr.write_record(HEADERS)?;
+
+    for text in texts {
+        wtr.serialize(text)?;
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum TextError {
+    Csv(csv::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Csv(err) => write!(f, "{}", err),
+            TextError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for TextError {}
+
+impl From<io::Error> for TextError {
+    fn from(err: io::Error) -> TextError {
+        TextError::Io(err)
+    }
+}
+
+impl From<csv::Error> for TextError {
+    fn from(err: csv::Error) -> TextError {
+        TextError::Csv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_time() -> Result<(), Box<dyn Error>> {
+        let long = "word ".repeat(450);
+        let mut texts = vec![Text::new("https://a.org", "One two,\n\nthree.")];
+        upsert(&mut texts, Text::new("https://b.org", ""));
+        upsert(&mut texts, Text::new("https://b.org", &long));
+
+        let mut buffer = Vec::new();
+        to_writer(&texts, &mut buffer)?;
+        let texts = from_reader(&buffer[..])?;
+
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts[0].words(), 3);
+        assert_eq!(texts[0].minutes(), 1);
+        assert_eq!(texts[0].text(), "One two,\n\nthree.");
+        assert_eq!(find(&texts, "https://b.org").map(|t| t.minutes()), Some(3));
+
+        Ok(())
+    }
+}